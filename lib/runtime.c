@@ -1,6 +1,7 @@
 #include <stdlib.h>
 #include <string.h>
 #include <stdio.h>
+#include <setjmp.h>
 
 char *concat(char *s1, char *s2) {
     size_t s1_size = strlen(s1);
@@ -23,6 +24,14 @@ void printInt(int x) {
     printf("%d\n", x);
 }
 
+void printLong(long x) {
+    printf("%ld\n", x);
+}
+
+void printDouble(double x) {
+    printf("%.17g\n", x);
+}
+
 int readInt() {
     int x;
     scanf("%d", &x);
@@ -30,6 +39,13 @@ int readInt() {
     return x;
 }
 
+double readDouble() {
+    double x;
+    scanf("%lf", &x);
+    getchar();
+    return x;
+}
+
 char *readString() {
     char *res = NULL;
     size_t size = 0;
@@ -44,7 +60,78 @@ char *readString() {
     return res;
 }
 
-void error() {
+__attribute__((noreturn)) void error() {
     printf("runtime error\n");
     exit(-1);
-}
\ No newline at end of file
+}
+typedef struct {
+    int length;
+    void *data;
+} array;
+
+array *new_array(int length, int elem_size) {
+    if (length < 0) {
+        error();
+    }
+    array *res = malloc(sizeof(array));
+    res->length = length;
+    res->data = calloc(length, elem_size);
+    return res;
+}
+
+array *new_string_array(int length) {
+    array *res = new_array(length, sizeof(char*));
+    char **data = res->data;
+    for (int i = 0; i < length; i++) {
+        data[i] = "";
+    }
+    return res;
+}
+
+void *new_object(int size) {
+    return calloc(1, size);
+}
+
+int compare_strings(char *s1, char *s2) {
+    return strcmp(s1, s2);
+}
+
+typedef struct handler {
+    jmp_buf env;
+    struct handler *prev;
+} handler;
+
+// Handlers of the try blocks being executed, innermost first
+static handler *handlers = NULL;
+static char *exception = NULL;
+
+// setjmp is called on the returned buffer by the generated code, in the frame of the try block
+void *push_handler() {
+    handler *res = malloc(sizeof(handler));
+    res->prev = handlers;
+    handlers = res;
+    return res->env;
+}
+
+void pop_handlers(int count) {
+    for (int i = 0; i < count; i++) {
+        handler *top = handlers;
+        handlers = top->prev;
+        free(top);
+    }
+}
+
+__attribute__((noreturn)) void throw(char *message) {
+    if (handlers == NULL) {
+        printf("uncaught exception: %s\n", message);
+        exit(-1);
+    }
+    exception = message;
+    _longjmp(handlers->env, 1);
+}
+
+// Called by the handler of the innermost try block once it catches the exception
+char *catch() {
+    pop_handlers(1);
+    return exception;
+}
@@ -3,12 +3,21 @@
 %struct.__sFILE = type { i8*, i32, i32, i16, i16, %struct.__sbuf, i32, i8*, i32 (i8*)*, i32 (i8*, i8*, i32)*, i64 (i8*, i64, i32)*, i32 (i8*, i8*, i32)*, %struct.__sbuf, %struct.__sFILEX*, i32, [3 x i8], [1 x i8], %struct.__sbuf, i32, i64 }
 %struct.__sFILEX = type opaque
 %struct.__sbuf = type { i8*, i32 }
+%struct.array = type { i32, i8* }
+%struct.handler = type { [37 x i32], %struct.handler* }
 
 @.str = private unnamed_addr constant [4 x i8] c"%s\0A\00", align 1
 @.str.1 = private unnamed_addr constant [4 x i8] c"%d\0A\00", align 1
 @.str.2 = private unnamed_addr constant [3 x i8] c"%d\00", align 1
 @__stdinp = external global %struct.__sFILE*, align 8
 @.str.3 = private unnamed_addr constant [15 x i8] c"runtime error\0A\00", align 1
+@.str.4 = private unnamed_addr constant [1 x i8] zeroinitializer, align 1
+@handlers = internal global %struct.handler* null, align 8
+@exception = internal global i8* null, align 8
+@.str.5 = private unnamed_addr constant [24 x i8] c"uncaught exception: %s\0A\00", align 1
+@.str.6 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@.str.7 = private unnamed_addr constant [7 x i8] c"%.17g\0A\00", align 1
+@.str.8 = private unnamed_addr constant [4 x i8] c"%lf\00", align 1
 
 ; Function Attrs: nounwind ssp uwtable
 define i8* @.concat(i8*, i8*) #0 {
@@ -101,6 +110,24 @@ define void @printInt(i32) #0 {
   ret void
 }
 
+; Function Attrs: nounwind ssp uwtable
+define void @printLong(i64) #0 {
+  %2 = alloca i64, align 8
+  store i64 %0, i64* %2, align 8
+  %3 = load i64, i64* %2, align 8
+  %4 = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.6, i32 0, i32 0), i64 %3)
+  ret void
+}
+
+; Function Attrs: nounwind ssp uwtable
+define void @printDouble(double) #0 {
+  %2 = alloca double, align 8
+  store double %0, double* %2, align 8
+  %3 = load double, double* %2, align 8
+  %4 = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([7 x i8], [7 x i8]* @.str.7, i32 0, i32 0), double %3)
+  ret void
+}
+
 ; Function Attrs: nounwind ssp uwtable
 define i32 @readInt() #0 {
   %1 = alloca i32, align 4
@@ -114,6 +141,15 @@ declare i32 @scanf(i8*, ...) #1
 
 declare i32 @getchar() #1
 
+; Function Attrs: nounwind ssp uwtable
+define double @readDouble() #0 {
+  %1 = alloca double, align 8
+  %2 = call i32 (i8*, ...) @scanf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @.str.8, i32 0, i32 0), double* %1)
+  %3 = call i32 @getchar()
+  %4 = load double, double* %1, align 8
+  ret double %4
+}
+
 ; Function Attrs: nounwind ssp uwtable
 define i8* @readString() #0 {
   %1 = alloca i8*, align 8
@@ -157,18 +193,143 @@ define i8* @readString() #0 {
 
 declare i64 @getline(i8**, i64*, %struct.__sFILE*) #1
 
-; Function Attrs: nounwind ssp uwtable
-define void @error() #0 {
+; Function Attrs: noreturn nounwind ssp uwtable
+define void @error() #7 {
   %1 = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([15 x i8], [15 x i8]* @.str.3, i32 0, i32 0))
   call void @exit(i32 -1) #6
   unreachable
-                                                  ; No predecessors!
-  ret void
 }
 
 ; Function Attrs: noreturn
 declare void @exit(i32) #4
 
+; Function Attrs: nounwind ssp uwtable
+define i8* @.new_array(i32, i32) #0 {
+  %3 = icmp slt i32 %0, 0
+  br i1 %3, label %4, label %5
+
+; <label>:4                                       ; preds = %2
+  call void @error()
+  br label %5
+
+; <label>:5                                       ; preds = %4, %2
+  %6 = call i8* @malloc(i64 16)
+  %7 = bitcast i8* %6 to %struct.array*
+  %8 = getelementptr inbounds %struct.array, %struct.array* %7, i32 0, i32 0
+  store i32 %0, i32* %8, align 8
+  %9 = sext i32 %0 to i64
+  %10 = sext i32 %1 to i64
+  %11 = call i8* @calloc(i64 %9, i64 %10)
+  %12 = getelementptr inbounds %struct.array, %struct.array* %7, i32 0, i32 1
+  store i8* %11, i8** %12, align 8
+  ret i8* %6
+}
+
+declare i8* @calloc(i64, i64) #1
+
+; Function Attrs: nounwind ssp uwtable
+define i8* @.new_string_array(i32) #0 {
+  %2 = call i8* @.new_array(i32 %0, i32 8)
+  %3 = bitcast i8* %2 to %struct.array*
+  %4 = getelementptr inbounds %struct.array, %struct.array* %3, i32 0, i32 1
+  %5 = load i8*, i8** %4, align 8
+  %6 = bitcast i8* %5 to i8**
+  br label %7
+
+; <label>:7                                       ; preds = %10, %1
+  %8 = phi i32 [ 0, %1 ], [ %13, %10 ]
+  %9 = icmp slt i32 %8, %0
+  br i1 %9, label %10, label %14
+
+; <label>:10                                      ; preds = %7
+  %11 = sext i32 %8 to i64
+  %12 = getelementptr inbounds i8*, i8** %6, i64 %11
+  store i8* getelementptr inbounds ([1 x i8], [1 x i8]* @.str.4, i32 0, i32 0), i8** %12, align 8
+  %13 = add nsw i32 %8, 1
+  br label %7
+
+; <label>:14                                      ; preds = %7
+  ret i8* %2
+}
+
+; Function Attrs: nounwind ssp uwtable
+define i8* @.new_object(i32) #0 {
+  %2 = sext i32 %0 to i64
+  %3 = call i8* @calloc(i64 1, i64 %2)
+  ret i8* %3
+}
+
+; Function Attrs: nounwind ssp uwtable
+define i32 @.compare_strings(i8*, i8*) #0 {
+  %3 = call i32 @strcmp(i8* %0, i8* %1)
+  ret i32 %3
+}
+
+declare i32 @strcmp(i8*, i8*) #1
+
+; Function Attrs: nounwind ssp uwtable
+define i8* @.push_handler() #0 {
+  %1 = call i8* @malloc(i64 160)
+  %2 = bitcast i8* %1 to %struct.handler*
+  %3 = load %struct.handler*, %struct.handler** @handlers, align 8
+  %4 = getelementptr inbounds %struct.handler, %struct.handler* %2, i32 0, i32 1
+  store %struct.handler* %3, %struct.handler** %4, align 8
+  store %struct.handler* %2, %struct.handler** @handlers, align 8
+  ret i8* %1
+}
+
+; Function Attrs: nounwind ssp uwtable
+define void @.pop_handlers(i32) #0 {
+  %2 = icmp sgt i32 %0, 0
+  br i1 %2, label %3, label %11
+
+; <label>:3                                       ; preds = %1, %3
+  %4 = phi i32 [ %9, %3 ], [ 0, %1 ]
+  %5 = load %struct.handler*, %struct.handler** @handlers, align 8
+  %6 = getelementptr inbounds %struct.handler, %struct.handler* %5, i32 0, i32 1
+  %7 = load %struct.handler*, %struct.handler** %6, align 8
+  store %struct.handler* %7, %struct.handler** @handlers, align 8
+  %8 = bitcast %struct.handler* %5 to i8*
+  call void @free(i8* %8)
+  %9 = add nsw i32 %4, 1
+  %10 = icmp slt i32 %9, %0
+  br i1 %10, label %3, label %11
+
+; <label>:11                                      ; preds = %3, %1
+  ret void
+}
+
+declare void @free(i8*) #1
+
+; Function Attrs: noreturn nounwind ssp uwtable
+define void @.throw(i8*) #7 {
+  %2 = load %struct.handler*, %struct.handler** @handlers, align 8
+  %3 = icmp eq %struct.handler* %2, null
+  br i1 %3, label %4, label %6
+
+; <label>:4                                       ; preds = %1
+  %5 = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([24 x i8], [24 x i8]* @.str.5, i32 0, i32 0), i8* %0)
+  call void @exit(i32 -1) #6
+  unreachable
+
+; <label>:6                                       ; preds = %1
+  store i8* %0, i8** @exception, align 8
+  %7 = getelementptr inbounds %struct.handler, %struct.handler* %2, i32 0, i32 0
+  %8 = getelementptr inbounds [37 x i32], [37 x i32]* %7, i32 0, i32 0
+  call void @_longjmp(i32* %8, i32 1) #6
+  unreachable
+}
+
+; Function Attrs: noreturn
+declare void @_longjmp(i32*, i32) #4
+
+; Function Attrs: nounwind ssp uwtable
+define i8* @.catch() #0 {
+  call void @.pop_handlers(i32 1)
+  %1 = load i8*, i8** @exception, align 8
+  ret i8* %1
+}
+
 attributes #0 = { nounwind ssp uwtable "disable-tail-calls"="false" "less-precise-fpmad"="false" "no-frame-pointer-elim"="true" "no-frame-pointer-elim-non-leaf" "no-infs-fp-math"="false" "no-nans-fp-math"="false" "stack-protector-buffer-size"="8" "target-cpu"="penryn" "target-features"="+cx16,+mmx,+sse,+sse2,+sse3,+sse4.1,+ssse3" "unsafe-fp-math"="false" "use-soft-float"="false" }
 attributes #1 = { "disable-tail-calls"="false" "less-precise-fpmad"="false" "no-frame-pointer-elim"="true" "no-frame-pointer-elim-non-leaf" "no-infs-fp-math"="false" "no-nans-fp-math"="false" "stack-protector-buffer-size"="8" "target-cpu"="penryn" "target-features"="+cx16,+mmx,+sse,+sse2,+sse3,+sse4.1,+ssse3" "unsafe-fp-math"="false" "use-soft-float"="false" }
 attributes #2 = { nounwind "disable-tail-calls"="false" "less-precise-fpmad"="false" "no-frame-pointer-elim"="true" "no-frame-pointer-elim-non-leaf" "no-infs-fp-math"="false" "no-nans-fp-math"="false" "stack-protector-buffer-size"="8" "target-cpu"="penryn" "target-features"="+cx16,+mmx,+sse,+sse2,+sse3,+sse4.1,+ssse3" "unsafe-fp-math"="false" "use-soft-float"="false" }
@@ -176,6 +337,7 @@ attributes #3 = { nounwind readnone }
 attributes #4 = { noreturn "disable-tail-calls"="false" "less-precise-fpmad"="false" "no-frame-pointer-elim"="true" "no-frame-pointer-elim-non-leaf" "no-infs-fp-math"="false" "no-nans-fp-math"="false" "stack-protector-buffer-size"="8" "target-cpu"="penryn" "target-features"="+cx16,+mmx,+sse,+sse2,+sse3,+sse4.1,+ssse3" "unsafe-fp-math"="false" "use-soft-float"="false" }
 attributes #5 = { nounwind }
 attributes #6 = { noreturn }
+attributes #7 = { noreturn nounwind ssp uwtable "disable-tail-calls"="false" "less-precise-fpmad"="false" "no-frame-pointer-elim"="true" "no-frame-pointer-elim-non-leaf" "no-infs-fp-math"="false" "no-nans-fp-math"="false" "stack-protector-buffer-size"="8" "target-cpu"="penryn" "target-features"="+cx16,+mmx,+sse,+sse2,+sse3,+sse4.1,+ssse3" "unsafe-fp-math"="false" "use-soft-float"="false" }
 
 !llvm.module.flags = !{!0}
 !llvm.ident = !{!1}
@@ -1,72 +1,212 @@
+use std::cell::RefCell;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span(pub usize, pub usize);
+
 #[derive(Debug, Clone)]
 pub struct Program(pub Vec<Def>);
 
 #[derive(Debug, Clone)]
 pub enum Def {
-    DFun(Type, String, Vec<Arg>, Vec<Stmt>),
+    DFun(Type, String, Vec<Arg>, Vec<Stmt>, Span),
+    DClass(String, Option<String>, Vec<Field>, Vec<Def>, Span),
+    DGlobal(Type, String, Option<Expr>, Span),
+    DConst(Type, String, Expr, Span),
+    // Function with type parameters, specialized for each instantiation
+    DGeneric(Vec<String>, Box<Def>),
+    DEnum(String, Vec<Variant>, Span),
+}
+
+impl Def {
+    pub fn span(&self) -> Span {
+        match *self {
+            Def::DFun(_, _, _, _, span) |
+            Def::DClass(_, _, _, _, span) |
+            Def::DGlobal(_, _, _, span) |
+            Def::DConst(_, _, _, span) |
+            Def::DEnum(_, _, span) => span,
+            Def::DGeneric(_, ref fun) => fun.span(),
+        }
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arg(pub Type, pub String, pub Span);
+
 #[derive(Debug, Clone)]
-pub struct Arg(pub Type, pub String);
+pub struct Field(pub Type, pub String, pub Span);
+
+// Variant of an enum, with the fields it carries
+#[derive(Debug, Clone)]
+pub struct Variant(pub String, pub Vec<Arg>, pub Span);
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Empty,
-    SDecl(Type, Vec<Item>),
-    SAss(String, Expr),
-    SInc(String),
-    SDecr(String),
-    SRet(Expr),
-    SVRet,
-    SIf(Expr, Box<Stmt>),
-    SIfElse(Expr, Box<Stmt>, Box<Stmt>),
-    SWhile(Expr, Box<Stmt>),
-    SExpr(Expr),
-    SBlock(Vec<Stmt>),
+    Empty(Span),
+    SDecl(Type, Vec<Item>, Span),
+    SAss(Expr, Expr, Span),
+    SInc(String, Span),
+    SDecr(String, Span),
+    // Compound assignment, like x += e
+    SOpAss(String, BinOp, Expr, Span),
+    SRet(Expr, Span),
+    SVRet(Span),
+    SIf(Expr, Box<Stmt>, Span),
+    SIfElse(Expr, Box<Stmt>, Box<Stmt>, Span),
+    SWhile(Expr, Box<Stmt>, Span),
+    SFor(Box<Stmt>, Expr, Box<Stmt>, Box<Stmt>, Span),
+    SForEach(Type, String, Expr, Box<Stmt>, Span),
+    SBreak(Span),
+    SContinue(Span),
+    SExpr(Expr, Span),
+    SBlock(Vec<Stmt>, Span),
+    SMatch(Expr, Vec<Case>, Span),
+    SThrow(Expr, Span),
+    // Guarded block, caught exception and handler block
+    STry(Box<Stmt>, Arg, Box<Stmt>, Span),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match *self {
+            Stmt::Empty(span) |
+            Stmt::SDecl(_, _, span) |
+            Stmt::SAss(_, _, span) |
+            Stmt::SInc(_, span) |
+            Stmt::SDecr(_, span) |
+            Stmt::SOpAss(_, _, _, span) |
+            Stmt::SRet(_, span) |
+            Stmt::SVRet(span) |
+            Stmt::SIf(_, _, span) |
+            Stmt::SIfElse(_, _, _, span) |
+            Stmt::SWhile(_, _, span) |
+            Stmt::SFor(_, _, _, _, span) |
+            Stmt::SForEach(_, _, _, _, span) |
+            Stmt::SBreak(span) |
+            Stmt::SContinue(span) |
+            Stmt::SExpr(_, span) |
+            Stmt::SBlock(_, span) |
+            Stmt::SMatch(_, _, span) |
+            Stmt::SThrow(_, span) |
+            Stmt::STry(_, _, _, span) => span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Case(pub Pattern, pub Stmt);
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    // Variant and the names bound to its fields, `_` binds nothing
+    PVariant(String, Vec<String>, Span),
+    PWildcard(Span),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match *self {
+            Pattern::PVariant(_, _, span) |
+            Pattern::PWildcard(span) => span,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Item {
-    NoInit(String),
-    Init(String, Expr),
+    NoInit(String, Span),
+    Init(String, Expr, Span),
 }
 
 impl Item {
     pub fn get_id(&self) -> String {
         match *self {
-            Item::NoInit(ref id) => id.clone(),
-            Item::Init(ref id, _) => id.clone(),
+            Item::NoInit(ref id, _) => id.clone(),
+            Item::Init(ref id, _, _) => id.clone(),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match *self {
+            Item::NoInit(_, span) |
+            Item::Init(_, _, span) => span,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
-    EVar(String),
-    EIntLit(i32),
-    EBoolLit(bool),
-    EStringLit(String),
-    EApp(String, Vec<Expr>),
-    ENeg(Box<Expr>),
-    ENot(Box<Expr>),
-    EPredef(Predef),
-    EOp(Box<Expr>, BinOp, Box<Expr>),
+    EVar(String, Span),
+    EIntLit(i32, Span),
+    ELongLit(i64, Span),
+    EDoubleLit(f64, Span),
+    EBoolLit(bool, Span),
+    EStringLit(String, Span),
+    EApp(String, Vec<Expr>, Span),
+    ENeg(Box<Expr>, Span),
+    ENot(Box<Expr>, Span),
+    EBitNot(Box<Expr>, Span),
+    EPredef(Predef, Span),
+    EOp(Box<Expr>, BinOp, Box<Expr>, Span),
+    ENewArray(Type, Box<Expr>, Span),
+    EIndex(Box<Expr>, Box<Expr>, Span),
+    EField(Box<Expr>, String, Span),
+    EMethodCall(Box<Expr>, String, Vec<Expr>, Span),
+    ENew(String, Span),
+    ENull(Type, Span),
+    ELambda(Vec<Arg>, Box<Expr>, Captures, Span),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+// Variables of the enclosing scopes used by a lambda, filled in by the type checker
+pub type Captures = RefCell<Vec<String>>;
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match *self {
+            Expr::EVar(_, span) |
+            Expr::EIntLit(_, span) |
+            Expr::ELongLit(_, span) |
+            Expr::EDoubleLit(_, span) |
+            Expr::EBoolLit(_, span) |
+            Expr::EStringLit(_, span) |
+            Expr::EApp(_, _, span) |
+            Expr::ENeg(_, span) |
+            Expr::ENot(_, span) |
+            Expr::EBitNot(_, span) |
+            Expr::EPredef(_, span) |
+            Expr::EOp(_, _, _, span) |
+            Expr::ENewArray(_, _, span) |
+            Expr::EIndex(_, _, span) |
+            Expr::EField(_, _, span) |
+            Expr::EMethodCall(_, _, _, span) |
+            Expr::ENew(_, span) |
+            Expr::ENull(_, span) |
+            Expr::ELambda(_, _, _, span) => span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Predef {
     PrintInt(Box<Expr>),
+    PrintLong(Box<Expr>),
+    PrintDouble(Box<Expr>),
     PrintString(Box<Expr>),
     Error,
     ReadInt,
+    ReadDouble,
     ReadString,
+    // Conversion of a numeric value to the given numeric type
+    Convert(Type, Box<Expr>),
 }
 
 impl Predef {
     pub fn get_type(&self) -> Type {
         match *self {
             Predef::ReadInt => Type::TInt,
+            Predef::ReadDouble => Type::TDouble,
             Predef::ReadString => Type::TString,
+            Predef::Convert(ref t, _) => t.clone(),
             _ => Type::TVoid,
         }
     }
@@ -87,15 +227,26 @@ pub enum BinOp {
     GE,
     And,
     Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Type {
     TInt,
+    TLong,
+    TDouble,
     TString,
     TBool,
     TVoid,
-    TFunc(Box<Type>, Vec<Type>)
+    TArray(Box<Type>),
+    TClass(String),
+    TFunc(Box<Type>, Vec<Type>),
+    // Type of expressions that failed to type check
+    TError,
 }
 
 
@@ -43,12 +43,42 @@ impl fmt::Display for Program {
 impl Print for Def {
     fn print(&self, indent: &String, fmt: &mut fmt::Formatter) {
         match *self {
-            Def::DFun(ref t, ref f, ref args, ref block) => {
+            Def::DFun(ref t, ref f, ref args, ref block, _) => {
                 write!(fmt, "{}{} {}({})", indent, t, f, print_list(&args)).unwrap();
                 writeln!(fmt, " {}", '{').unwrap();
                 block.print(&format!("\t{}", indent), fmt);
                 writeln!(fmt, "{}", '}').unwrap();
-            }
+            },
+            Def::DClass(ref name, ref parent, ref fields, ref methods, _) => {
+                write!(fmt, "{}class {}", indent, name).unwrap();
+                if let Some(ref parent) = *parent {
+                    write!(fmt, " extends {}", parent).unwrap();
+                }
+                writeln!(fmt, " {}", '{').unwrap();
+                for field in fields {
+                    writeln!(fmt, "\t{}{};", indent, field).unwrap();
+                }
+                methods.print(&format!("\t{}", indent), fmt);
+                writeln!(fmt, "{}{}", indent, '}').unwrap();
+            },
+            Def::DGlobal(ref t, ref name, Some(ref init), _) => writeln!(fmt, "{}{} {} = {};", indent, t, name, init).unwrap(),
+            Def::DGlobal(ref t, ref name, None, _) => writeln!(fmt, "{}{} {};", indent, t, name).unwrap(),
+            Def::DConst(ref t, ref name, ref init, _) => writeln!(fmt, "{}const {} {} = {};", indent, t, name, init).unwrap(),
+            Def::DGeneric(ref type_params, ref fun) => {
+                if let Def::DFun(ref t, ref f, ref args, ref block, _) = **fun {
+                    write!(fmt, "{}{} {}<{}>({})", indent, t, f, print_list(type_params), print_list(&args)).unwrap();
+                    writeln!(fmt, " {}", '{').unwrap();
+                    block.print(&format!("\t{}", indent), fmt);
+                    writeln!(fmt, "{}", '}').unwrap();
+                }
+            },
+            Def::DEnum(ref name, ref variants, _) => {
+                writeln!(fmt, "{}enum {} {}", indent, name, '{').unwrap();
+                for variant in variants {
+                    writeln!(fmt, "\t{}{},", indent, variant).unwrap();
+                }
+                writeln!(fmt, "{}{}", indent, '}').unwrap();
+            },
         }
     }
 }
@@ -64,33 +94,62 @@ impl Print for Stmt {
     fn print(&self, indent: &String, fmt: &mut fmt::Formatter) {
         write!(fmt, "{}", indent).unwrap();
         match *self {
-            Stmt::SDecl(ref t, ref items) => writeln!(fmt, "{} {};", t, print_list(&items)).unwrap(),
-            Stmt::SAss(ref var, ref expr) => writeln!(fmt, "{} = {};", var, *expr).unwrap(),
-            Stmt::SInc(ref var) => writeln!(fmt, "{}++;", var).unwrap(),
-            Stmt::SDecr(ref var) => writeln!(fmt, "{}--;", var).unwrap(),
-            Stmt::SExpr(ref expr) => writeln!(fmt, "{};", *expr).unwrap(),
-            Stmt::SIf(ref expr, ref block) => {
+            Stmt::SDecl(ref t, ref items, _) => writeln!(fmt, "{} {};", t, print_list(&items)).unwrap(),
+            Stmt::SAss(ref var, ref expr, _) => writeln!(fmt, "{} = {};", var, *expr).unwrap(),
+            Stmt::SInc(ref var, _) => writeln!(fmt, "{}++;", var).unwrap(),
+            Stmt::SDecr(ref var, _) => writeln!(fmt, "{}--;", var).unwrap(),
+            Stmt::SOpAss(ref var, ref op, ref expr, _) => writeln!(fmt, "{} {}= {};", var, op, *expr).unwrap(),
+            Stmt::SExpr(ref expr, _) => writeln!(fmt, "{};", *expr).unwrap(),
+            Stmt::SIf(ref expr, ref block, _) => {
                 writeln!(fmt, "if ({})", *expr).unwrap();
                 block.print(indent, fmt);
             },
-            Stmt::SIfElse(ref expr, ref if_block, ref else_block) => {
+            Stmt::SIfElse(ref expr, ref if_block, ref else_block, _) => {
                 writeln!(fmt, "if ({})", *expr).unwrap();
                 if_block.print(indent, fmt);
                 writeln!(fmt, "{}else", indent).unwrap();
                 else_block.print(indent, fmt);
             },
-            Stmt::SWhile(ref expr, ref block) => {
+            Stmt::SWhile(ref expr, ref block, _) => {
                 writeln!(fmt, "while ({})", *expr).unwrap();
                 block.print(indent, fmt);
             },
-            Stmt::SVRet => writeln!(fmt, "return;").unwrap(),
-            Stmt::SRet(ref expr) => writeln!(fmt, "return {};", *expr).unwrap(),
-            Stmt::SBlock(ref stmts) => {
+            Stmt::SFor(ref init, ref cond, ref step, ref block, _) => {
+                let init = format!("{}", init);
+                let step = format!("{}", step);
+                writeln!(fmt, "for ({}; {}; {})", init.trim_right_matches(|c| c == ';' || c == '\n'), *cond,
+                         step.trim_right_matches(|c| c == ';' || c == '\n')).unwrap();
+                block.print(indent, fmt);
+            },
+            Stmt::SForEach(ref t, ref var, ref expr, ref block, _) => {
+                writeln!(fmt, "for ({} {} : {})", t, var, *expr).unwrap();
+                block.print(indent, fmt);
+            },
+            Stmt::SBreak(_) => writeln!(fmt, "break;").unwrap(),
+            Stmt::SContinue(_) => writeln!(fmt, "continue;").unwrap(),
+            Stmt::SVRet(_) => writeln!(fmt, "return;").unwrap(),
+            Stmt::SRet(ref expr, _) => writeln!(fmt, "return {};", *expr).unwrap(),
+            Stmt::SBlock(ref stmts, _) => {
                 writeln!(fmt, "{}", '{').unwrap();
                 stmts.print(&format!("\t{}", indent), fmt);
                 writeln!(fmt, "{}{}", indent, '}').unwrap();
             },
-            Stmt::Empty => writeln!(fmt, ";").unwrap(),
+            Stmt::SMatch(ref expr, ref cases, _) => {
+                writeln!(fmt, "match ({}) {}", *expr, '{').unwrap();
+                for &Case(ref pattern, ref stmt) in cases {
+                    writeln!(fmt, "\t{}{} =>", indent, pattern).unwrap();
+                    stmt.print(&format!("\t\t{}", indent), fmt);
+                }
+                writeln!(fmt, "{}{}", indent, '}').unwrap();
+            },
+            Stmt::SThrow(ref expr, _) => writeln!(fmt, "throw {};", *expr).unwrap(),
+            Stmt::STry(ref block, ref arg, ref handler, _) => {
+                writeln!(fmt, "try").unwrap();
+                block.print(indent, fmt);
+                writeln!(fmt, "{}catch ({})", indent, arg).unwrap();
+                handler.print(indent, fmt);
+            },
+            Stmt::Empty(_) => writeln!(fmt, ";").unwrap(),
         };
     }
 }
@@ -107,9 +166,15 @@ impl Print for Predef {
         match *self {
             Predef::Error => write!(fmt, "error()").unwrap(),
             Predef::PrintInt(ref expr) => write!(fmt, "printInt({})", *expr).unwrap(),
+            Predef::PrintLong(ref expr) => write!(fmt, "printLong({})", *expr).unwrap(),
+            Predef::PrintDouble(ref expr) => write!(fmt, "printDouble({})", *expr).unwrap(),
             Predef::PrintString(ref expr) => write!(fmt, "printString({})", *expr).unwrap(),
             Predef::ReadInt => write!(fmt, "readInt()").unwrap(),
+            Predef::ReadDouble => write!(fmt, "readDouble()").unwrap(),
             Predef::ReadString => write!(fmt, "readString()").unwrap(),
+            Predef::Convert(Type::TInt, ref expr) => write!(fmt, "toInt({})", *expr).unwrap(),
+            Predef::Convert(Type::TLong, ref expr) => write!(fmt, "toLong({})", *expr).unwrap(),
+            Predef::Convert(_, ref expr) => write!(fmt, "toDouble({})", *expr).unwrap(),
         };
     }
 }
@@ -124,15 +189,25 @@ impl fmt::Display for Predef {
 impl Display for Expr {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let s = match *self {
-            Expr::EVar(ref i) => format!("{}", i),
-            Expr::EIntLit(ref i) => format!("{}", i),
-            Expr::EBoolLit(ref b) => format!("{}", b),
-            Expr::EStringLit(ref s) => format!("\"{}\"", s),
-            Expr::EApp(ref f, ref args) => format!("{}({})", f, print_list(args)),
-            Expr::ENeg(ref e) => format!("-{}", *e),
-            Expr::ENot(ref e) => format!("!{}", *e),
-            Expr::EOp(ref lhs, ref op, ref rhs) => format!("({} {} {})", *lhs, op, *rhs),
-            Expr::EPredef(ref predef) => format!("{}", predef),
+            Expr::EVar(ref i, _) => format!("{}", i),
+            Expr::EIntLit(ref i, _) => format!("{}", i),
+            Expr::ELongLit(ref i, _) => format!("{}L", i),
+            Expr::EDoubleLit(ref d, _) => format!("{:?}", d),
+            Expr::EBoolLit(ref b, _) => format!("{}", b),
+            Expr::EStringLit(ref s, _) => format!("{:?}", s),
+            Expr::EApp(ref f, ref args, _) => format!("{}({})", f, print_list(args)),
+            Expr::ENeg(ref e, _) => format!("-{}", *e),
+            Expr::ENot(ref e, _) => format!("!{}", *e),
+            Expr::EBitNot(ref e, _) => format!("~{}", *e),
+            Expr::EOp(ref lhs, ref op, ref rhs, _) => format!("({} {} {})", *lhs, op, *rhs),
+            Expr::EPredef(ref predef, _) => format!("{}", predef),
+            Expr::ENewArray(ref t, ref size, _) => format!("new {}[{}]", t, *size),
+            Expr::EIndex(ref array, ref index, _) => format!("{}[{}]", *array, *index),
+            Expr::EField(ref expr, ref field, _) => format!("{}.{}", *expr, field),
+            Expr::EMethodCall(ref object, ref method, ref args, _) => format!("{}.{}({})", *object, method, print_list(args)),
+            Expr::ENew(ref class, _) => format!("new {}", class),
+            Expr::ENull(ref t, _) => format!("({})null", t),
+            Expr::ELambda(ref args, ref body, _, _) => format!("({}) -> {}", print_list(args), *body),
         };
         write!(fmt, "{}", s)
     }
@@ -141,8 +216,8 @@ impl Display for Expr {
 impl Display for Item {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let s = match *self {
-            Item::Init(ref var, ref e) => format!("{} = {}", var, e),
-            Item::NoInit(ref var) => format!("{}", var),
+            Item::Init(ref var, ref e, _) => format!("{} = {}", var, e),
+            Item::NoInit(ref var, _) => format!("{}", var),
         };
         write!(fmt, "{}", s)
     }
@@ -150,7 +225,30 @@ impl Display for Item {
 
 impl Display for Arg {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let Arg(ref t, ref id) = *self;
+        let Arg(ref t, ref id, _) = *self;
+        write!(fmt, "{} {}", t, id)
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let Variant(ref id, ref fields, _) = *self;
+        write!(fmt, "{}({})", id, print_list(fields))
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Pattern::PVariant(ref variant, ref bindings, _) => write!(fmt, "{}({})", variant, print_list(bindings)),
+            Pattern::PWildcard(_) => write!(fmt, "_"),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let Field(ref t, ref id, _) = *self;
         write!(fmt, "{} {}", t, id)
     }
 }
@@ -159,10 +257,15 @@ impl fmt::Display for Type {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let s = match *self {
             Type::TInt => "int",
+            Type::TLong => "long",
+            Type::TDouble => "double",
             Type::TString => "string",
             Type::TBool => "boolean",
             Type::TVoid => "void",
-            _ => "function",
+            Type::TArray(ref t) => return write!(fmt, "{}[]", t),
+            Type::TClass(ref name) => name.as_str(),
+            Type::TFunc(ref ret, ref args) => return write!(fmt, "({}) -> {}", print_list(args), ret),
+            Type::TError => "<error>",
         };
         write!(fmt, "{}", s)
     }
@@ -184,6 +287,11 @@ impl fmt::Display for BinOp {
             BinOp::NEQ => "!=",
             BinOp::And => "&&",
             BinOp::Or => "||",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
         };
         write!(fmt, "{}", s)
     }
@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::env;
+use std::process::*;
+use std::io::Read;
+use std::path::Path;
+use std::io::Write;
+use latte::diagnostics::Diagnostic;
+use latte::{Lint, LintConfig};
+
+macro_rules! println_stderr(
+    ($($arg:tt)*) => { {
+        let r = writeln!(&mut ::std::io::stderr(), $($arg)*);
+        r.expect("failed printing to stderr");
+    } }
+);
+
+pub fn print_err(err: String) -> ! {
+    println_stderr!("ERROR");
+    println_stderr!("{}", err);
+    exit(-1);
+}
+
+pub fn print_diagnostics(diagnostics: &[Diagnostic], filename: &str, source: &str) -> ! {
+    for diagnostic in diagnostics {
+        println_stderr!("{}", diagnostic.to_json(filename, source));
+    }
+    exit(-1);
+}
+
+pub fn print_warnings(diagnostics: &[Diagnostic], filename: &str, source: &str) {
+    for diagnostic in diagnostics {
+        match get_error_format() {
+            ErrorFormat::Human => println_stderr!("{}", diagnostic.report(filename, source)),
+            ErrorFormat::Json => println_stderr!("{}", diagnostic.to_json(filename, source)),
+        }
+    }
+}
+
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+pub fn get_error_format() -> ErrorFormat {
+    if env::args().skip(1).any(|arg| arg == "--error-format=json") {
+        ErrorFormat::Json
+    } else {
+        ErrorFormat::Human
+    }
+}
+
+// -W<lint> and -A<lint> enable and silence a lint, later flags override
+// earlier ones. "all" stands for every lint, -Werror makes warnings fatal.
+pub fn get_lint_config() -> LintConfig {
+    let mut config = LintConfig::new();
+    for arg in env::args().skip(1) {
+        let (warn, name) = if arg.starts_with("-W") {
+            (true, arg[2..].to_string())
+        } else if arg.starts_with("-A") {
+            (false, arg[2..].to_string())
+        } else {
+            continue;
+        };
+        match (warn, name.as_str()) {
+            (true, "error") => config.warnings_as_errors = true,
+            (true, "all") => config.warn_all(),
+            (false, "all") => config.allow_all(),
+            (_, name) => match Lint::from_name(name) {
+                Some(lint) if warn => config.warn(lint),
+                Some(lint) => config.allow(lint),
+                None => print_err(format!("Unknown lint {}", name)),
+            },
+        }
+    }
+    config
+}
+
+// First argument that isn't an option
+pub fn get_input_filename() -> String {
+    env::args().skip(1).find(|arg| !arg.starts_with("-")).expect("Filename not given")
+}
+
+pub fn get_input() -> String {
+    let mut to_parse = String::new();
+    if let Some(ref filename) = env::args().skip(1).find(|arg| !arg.starts_with("-")) {
+        if let Ok(mut input) = File::open(filename) {
+            input.read_to_string(&mut to_parse).unwrap();
+        } else {
+            print_err(format!("Couldn't open file {}", filename));
+        }
+    } else {
+        print_err(format!("Filename not given"));
+    }
+    to_parse
+}
+
+pub fn get_output_directory() -> String {
+    let ref arg1 = get_input_filename();
+    let path = Path::new(arg1);
+    let parent = path.parent().unwrap_or(Path::new("."));
+    if parent.to_str().unwrap() == "" {
+        String::from("./")
+    } else {
+        format!("{}/", parent.to_str().unwrap())
+    }
+}
+
+pub fn get_output_filename(extension: &str) -> String {
+    let no_ext = get_filename_no_ext();
+    let parent = get_output_directory();
+    format!("{}{}{}", parent, no_ext, extension)
+}
+
+pub fn get_filename_no_ext() -> String {
+    let ref arg1 = get_input_filename();
+    let path = Path::new(arg1);
+    let no_ext = path.file_stem().expect("Error opening file");
+    format!("{}", no_ext.to_str().unwrap())
+}
\ No newline at end of file
@@ -1,12 +1,14 @@
-use code_generation::generation_context::{CGContext, Val, Register, Const};
-use semantic_analysis::type_checker::{Returns};
-use std::fs::File;
+use code_generation::generation_context::{CGContext, Val, Register, Const, Class, Lambda};
+use semantic_analysis::cfg::Cfg;
+use semantic_analysis::generics::{Substitute, infer_type_args, instantiate, mangle};
+use std::io;
+use std::io::Write;
 use ast::*;
 
-pub fn generate(out: &mut File, p: &Program) {
+pub fn generate<W: Write>(out: &mut W, p: &Program) -> io::Result<()> {
     let mut context = CGContext::new();
     p.generate(&mut context);
-    context.write(out).expect("Couldn't write to file");
+    context.write(out)
 }
 
 trait Generator<T> {
@@ -18,53 +20,264 @@ impl Generator<()> for Program {
         let Program(ref defs) = *self;
 
         context.add_code(format!("declare void @printInt(i32)"));
+        context.add_code(format!("declare void @printLong(i64)"));
+        context.add_code(format!("declare void @printDouble(double)"));
         context.add_code(format!("declare void @printString(i8*)"));
-        context.add_code(format!("declare void @error()"));
+        context.add_code(format!("declare void @error() noreturn"));
         context.add_code(format!("declare i32 @readInt()"));
+        context.add_code(format!("declare double @readDouble()"));
         context.add_code(format!("declare i8* @readString()"));
         context.add_code(format!("declare i8* @.concat(i8*, i8*)"));
+        context.add_code(format!("declare i8* @.new_array(i32, i32)"));
+        context.add_code(format!("declare i8* @.new_string_array(i32)"));
+        context.add_code(format!("declare i8* @.new_object(i32)"));
+        context.add_code(format!("declare i32 @.compare_strings(i8*, i8*)"));
+        context.add_code(format!("declare i8* @.push_handler()"));
+        context.add_code(format!("declare void @.pop_handlers(i32)"));
+        context.add_code(format!("declare void @.throw(i8*) noreturn"));
+        context.add_code(format!("declare i8* @.catch()"));
+        context.add_code(format!("declare i32 @_setjmp(i8*) returns_twice"));
+        context.add_code(format!("%closure = type {{ i8*, i8* }}"));
 
         for def in defs {
             match def {
-                &Def::DFun(ref ret_type, ref name, _, _) => context.add_function(name, ret_type),
+                &Def::DFun(ref ret_type, ref name, ref args, _, _) => {
+                    let fun_type = Type::TFunc(Box::new(ret_type.clone()), args.iter().map(|arg| arg.0.clone()).collect());
+                    context.add_function(name, &fun_type)
+                },
+                &Def::DClass(ref name, _, _, _, _) => declare_class(name, defs, context),
+                &Def::DGlobal(ref t, ref name, ref init, _) => generate_global(t, name, init.as_ref(), "global", context),
+                &Def::DConst(ref t, ref name, ref init, _) => generate_global(t, name, Some(init), "constant", context),
+                &Def::DGeneric(ref type_params, ref fun) => if let Def::DFun(_, ref name, _, _, _) = **fun {
+                    context.add_generic(name, type_params, fun)
+                },
+                &Def::DEnum(ref name, ref variants, _) => declare_enum(name, variants, context),
             }
         }
 
         for def in defs {
             context.in_new_function_scope(|context| def.generate(context));
         }
+
+        // Lambdas and instances of generic functions can use each other, so generating one may queue more of them
+        loop {
+            let instances = context.take_instances();
+            let lambdas = context.take_lambdas();
+            if instances.is_empty() && lambdas.is_empty() {
+                break;
+            }
+            for (name, generic, type_args) in instances {
+                let instance = {
+                    let &(ref type_params, ref fun) = context.get_generic(&generic);
+                    let bindings = type_params.iter().cloned().zip(type_args).collect();
+                    instantiate(fun, &name, &bindings)
+                };
+                context.in_new_function_scope(|context| instance.generate(context));
+            }
+            for lambda in &lambdas {
+                context.in_new_function_scope(|context| generate_lambda(lambda, context));
+            }
+        }
+
+        for name in context.function_values() {
+            generate_function_value(&name, context);
+        }
+    }
+}
+
+// Initializers of globals are literals after constant folding
+fn generate_global(t: &Type, name: &String, init: Option<&Expr>, kind: &str, context: &mut CGContext) {
+    context.add_global(name, t);
+    let value = match init {
+        Some(&Expr::EIntLit(x, _)) => format!("{}", x),
+        Some(&Expr::ELongLit(x, _)) => format!("{}", x),
+        Some(&Expr::EDoubleLit(x, _)) => format!("{}", Const::DConst(x)),
+        Some(&Expr::EBoolLit(x, _)) => format!("{}", x),
+        Some(&Expr::EStringLit(ref s, _)) => string_constant(s, context),
+        None if *t == Type::TInt || *t == Type::TLong => String::from("0"),
+        None if *t == Type::TDouble => format!("{}", Const::DConst(0.0)),
+        None if *t == Type::TBool => String::from("false"),
+        None if *t == Type::TString => string_constant(&String::new(), context),
+        _ => String::from("null"),
+    };
+    context.add_code(format!("@{} = {} {} {}", name, kind, t.to_llvm(), value));
+}
+
+fn string_constant(s: &String, context: &mut CGContext) -> String {
+    let c = context.get_const(s);
+    format!("getelementptr inbounds ([{} x i8], [{} x i8]* {}, i64 0, i64 0)", s.len() + 1, s.len() + 1, c)
+}
+
+fn declare_class(name: &String, defs: &Vec<Def>, context: &mut CGContext) {
+    if context.has_class(name) {
+        return;
+    }
+    for def in defs {
+        match *def {
+            Def::DClass(ref class, ref parent, ref fields, ref methods, _) if class == name => {
+                let mut layout = match *parent {
+                    Some(ref parent) => {
+                        declare_class(parent, defs, context);
+                        context.get_class(parent)
+                    },
+                    None => Class { fields: vec![], methods: vec![] },
+                };
+                layout.fields.extend(fields.iter().cloned());
+                for method in methods {
+                    if let Def::DFun(ref ret_type, ref method_name, ref args, _, _) = *method {
+                        let method_type = Type::TFunc(Box::new(ret_type.clone()), args.iter().map(|arg| arg.0.clone()).collect());
+                        let entry = (method_name.clone(), class.clone(), method_type);
+                        match layout.methods.iter().position(|m| &m.0 == method_name) {
+                            Some(i) => layout.methods[i] = entry,
+                            None => layout.methods.push(entry),
+                        }
+                    }
+                }
+
+                let field_types = layout.fields.iter().map(|f| format!(", {}", f.0.to_llvm())).collect::<String>();
+                let method_types = layout.methods.iter().map(|m| method_to_llvm(&m.2)).collect::<Vec<String>>();
+                let vtable = layout.methods.iter()
+                    .map(|m| format!("{} @{}.{}", method_to_llvm(&m.2), m.1, m.0))
+                    .collect::<Vec<String>>();
+                context.add_code(format!("%class.{} = type {{ %vtable.{}*{} }}", name, name, field_types));
+                context.add_code(format!("%vtable.{} = type {{ {} }}", name, method_types.join(", ")));
+                context.add_code(format!("@vtable.{} = global %vtable.{} {{ {} }}", name, name, vtable.join(", ")));
+                context.add_class(name, layout);
+            },
+            _ => (),
+        }
+    }
+}
+
+// Values of enums are records starting with the tag, which is the index of their variant
+fn declare_enum(name: &String, variants: &Vec<Variant>, context: &mut CGContext) {
+    for (tag, &Variant(ref variant, ref fields, _)) in variants.iter().enumerate() {
+        let field_types = fields.iter().map(|f| format!(", {}", f.0.to_llvm())).collect::<String>();
+        context.add_code(format!("{} = type {{ i32{} }}", variant_struct(name, variant), field_types));
+        context.add_variant(variant, name, tag as i32, fields.iter().map(|f| f.0.clone()).collect());
+    }
+}
+
+fn variant_struct(enum_name: &String, variant: &String) -> String {
+    format!("%enum.{}.{}", enum_name, variant)
+}
+
+// Methods take the object and closures take their environment as the first argument
+fn method_to_llvm(method_type: &Type) -> String {
+    match *method_type {
+        Type::TFunc(ref ret_type, ref args) => {
+            let args = args.iter().map(|arg| format!(", {}", arg.to_llvm())).collect::<String>();
+            format!("{} (i8*{})*", ret_type.to_llvm(), args)
+        },
+        _ => unreachable!(),
     }
 }
 
 impl Generator<()> for Def {
     fn generate(&self, context: &mut CGContext) {
         match *self {
-            Def::DFun(ref ret_type, ref name, ref args, ref stmts) => {
-                let mut code = format!("define {} @{}(", ret_type.to_llvm(), name);
-                for (i, arg) in args.iter().enumerate() {
-                    if i > 0 {
-                        code = format!("{}, ", code);
+            Def::DFun(ref ret_type, ref name, ref args, ref stmts, _) =>
+                generate_function(ret_type, name, args, stmts, context),
+            Def::DClass(ref class, _, _, ref methods, _) => {
+                context.set_current_class(Some(class.clone()));
+                for method in methods {
+                    if let Def::DFun(ref ret_type, ref name, ref args, ref stmts, _) = *method {
+                        let mut method_args = vec![Arg(Type::TClass(class.clone()), String::from("self"), method.span())];
+                        method_args.extend(args.iter().cloned());
+                        let name = format!("{}.{}", class, name);
+                        context.in_new_function_scope(|context| generate_function(ret_type, &name, &method_args, stmts, context));
                     }
-                    code = format!("{}{}", code, arg.generate(context));
-                }
-                code = format!("{}) {}", code, '{');
-                context.add_code(code);
-                args.iter().map(|arg| generate_local_var(context, arg)).collect::<Vec<()>>();
-                stmts.generate(context);
-                if ret_type == &Type::TVoid {
-                    context.add_code(String::from("ret void"));
                 }
-                context.add_code(String::from("}"));
-            }
+                context.set_current_class(None);
+            },
+            Def::DGlobal(_, _, _, _) |
+            Def::DConst(_, _, _, _) |
+            Def::DGeneric(_, _) |
+            Def::DEnum(_, _, _) => (),
         }
     }
 }
 
+fn generate_lambda(lambda: &Lambda, context: &mut CGContext) {
+    context.set_current_class(lambda.class.clone());
+    let env = context.next_register();
+    let mut params = vec![format!("i8* {}", env)];
+    params.extend(lambda.args.iter().map(|arg| arg.generate(context)));
+    context.add_code(format!("define {} @{}({}) {{", lambda.ret_type.to_llvm(), lambda.name, params.join(", ")));
+    for arg in &lambda.args {
+        generate_local_var(context, arg);
+    }
+    if !lambda.captures.is_empty() {
+        let env_type = environment_type(&lambda.captures);
+        let cast = generate_assign(context, format!("bitcast i8* {} to {}*", env, env_type));
+        for (i, &(ref id, ref var_type)) in lambda.captures.iter().enumerate() {
+            let llvm_type = var_type.to_llvm();
+            let ptr = generate_assign(context, format!("getelementptr {}, {}* {}, i32 0, i32 {}", env_type, env_type, cast, i));
+            let val = generate_assign(context, format!("load {}, {}* {}", llvm_type, llvm_type, ptr));
+            let reg = context.add(id, var_type);
+            context.add_code(format!("{} = alloca {}", reg, llvm_type));
+            store_var(id, &Val::Register(val), &llvm_type, context);
+        }
+    }
+    let val = lambda.body.generate(context);
+    match lambda.ret_type {
+        Type::TVoid => context.add_code(String::from("ret void")),
+        ref ret_type => context.add_code(format!("ret {} {}", ret_type.to_llvm(), val)),
+    }
+    context.add_code(String::from("}"));
+    context.set_current_class(None);
+}
+
+// Closure record of a top level function, whose code ignores the environment
+fn generate_function_value(name: &String, context: &mut CGContext) {
+    let fun_type = context.get_function_type(name);
+    let ret_type = fun_type.ret_type();
+    let env = context.next_register();
+    let args = match fun_type {
+        Type::TFunc(_, ref arg_types) =>
+            arg_types.iter().map(|t| (Val::Register(context.next_register()), t.clone())).collect::<Vec<(Val, Type)>>(),
+        _ => unreachable!(),
+    };
+    let mut params = vec![format!("i8* {}", env)];
+    params.extend(args.iter().map(|&(ref val, ref t)| format!("{} {}", t.to_llvm(), val)));
+    context.add_code(format!("define {} @.code.{}({}) {{", ret_type.to_llvm(), name, params.join(", ")));
+    let res = generate_call(&format!("@{}", name), &ret_type, args, context);
+    match ret_type {
+        Type::TVoid => context.add_code(String::from("ret void")),
+        ref ret_type => context.add_code(format!("ret {} {}", ret_type.to_llvm(), res)),
+    }
+    context.add_code(String::from("}"));
+    context.add_code(format!("@.closure.{} = global %closure {{ i8* bitcast ({} @.code.{} to i8*), i8* null }}",
+                             name, method_to_llvm(&fun_type), name));
+}
+
+fn generate_function(ret_type: &Type, name: &String, args: &Vec<Arg>, stmts: &Vec<Stmt>, context: &mut CGContext) {
+    let mut code = format!("define {} @{}(", ret_type.to_llvm(), name);
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            code = format!("{}, ", code);
+        }
+        code = format!("{}{}", code, arg.generate(context));
+    }
+    code = format!("{}) {}", code, '{');
+    context.add_code(code);
+    args.iter().map(|arg| generate_local_var(context, arg)).collect::<Vec<()>>();
+    stmts.generate(context);
+    // Terminates the last block, whether or not it is reachable
+    if ret_type == &Type::TVoid {
+        context.add_code(String::from("ret void"));
+    } else {
+        context.add_code(String::from("unreachable"));
+    }
+    context.add_code(String::from("}"));
+}
+
 impl Generator<()> for Vec<Stmt> {
     fn generate(&self, context: &mut CGContext) {
         for stmt in self {
             stmt.generate(context);
-            if stmt.check_return(){
+            // The statements following it are unreachable
+            if !Cfg::completes(stmt) {
                 break;
             }
         }
@@ -73,14 +286,14 @@ impl Generator<()> for Vec<Stmt> {
 
 impl Generator<String> for Arg {
     fn generate(&self, context: &mut CGContext) -> String {
-        let Arg(ref arg_type, ref id) = *self;
+        let Arg(ref arg_type, ref id, _) = *self;
         let reg = context.add(id, arg_type);
         format!("{} {}", arg_type.to_llvm(), reg)
     }
 }
 
 fn generate_local_var(context: &mut CGContext, arg: &Arg) {
-    let Arg(ref arg_type, ref id) = *arg;
+    let Arg(ref arg_type, ref id, _) = *arg;
     let val_reg = context.get_register(id);
     let addr_reg = generate_assign(context, format!("alloca {}", arg_type.to_llvm()));
     context.switch_reg(id, addr_reg);
@@ -90,40 +303,60 @@ fn generate_local_var(context: &mut CGContext, arg: &Arg) {
 impl Generator<()> for Stmt {
     fn generate(&self, context: &mut CGContext) {
         match *self {
-            Stmt::SAss(ref id, ref expr) => {
+            Stmt::SAss(Expr::EVar(ref id, _), ref expr, _) => {
                 let val = expr.generate(context);
                 let rhs_type = expr.get_type(context).to_llvm();
                 store_var(id, &val, &rhs_type, context)
             },
-            Stmt::SDecl(ref item_type, ref items) =>
+            Stmt::SAss(Expr::EIndex(ref array, ref index, _), ref expr, _) => {
+                let ptr = generate_element_ptr(array, index, context);
+                let val = expr.generate(context);
+                let rhs_type = expr.get_type(context).to_llvm();
+                context.add_code(format!("store {} {}, {}* {}", rhs_type, val, rhs_type, ptr))
+            },
+            Stmt::SAss(Expr::EField(ref object, ref field, _), ref expr, _) => {
+                let ptr = generate_field_ptr(object, field, context);
+                let val = expr.generate(context);
+                let rhs_type = expr.get_type(context).to_llvm();
+                context.add_code(format!("store {} {}, {}* {}", rhs_type, val, rhs_type, ptr))
+            },
+            Stmt::SAss(_, _, _) => unreachable!(),
+            Stmt::SDecl(ref item_type, ref items, _) =>
                 for item in items {
                     generate_item(item_type, item, context);
                 },
-            Stmt::SInc(ref id) => manipulate_variable(id, String::from("add i32 1"), context),
-            Stmt::SDecr(ref id) => manipulate_variable(id, String::from("add i32 -1"), context),
-            Stmt::SExpr(ref expr) => {
+            Stmt::SInc(ref id, _) => manipulate_variable(id, String::from("add i32 1"), context),
+            Stmt::SDecr(ref id, _) => manipulate_variable(id, String::from("add i32 -1"), context),
+            Stmt::SOpAss(ref id, op, ref expr, _) => generate_compound_assignment(id, op, expr, context),
+            Stmt::SExpr(ref expr, _) => {
                 expr.generate(context);
             },
-            Stmt::SVRet => context.add_code(format!("ret void")),
-            Stmt::SRet(ref expr) => {
+            Stmt::SVRet(_) => {
+                let handlers = context.handlers();
+                generate_pop_handlers(handlers, context);
+                context.add_code(format!("ret void"));
+            },
+            Stmt::SRet(ref expr, _) => {
                 let val = expr.generate(context);
                 let val_type = expr.get_type(context).to_llvm();
+                let handlers = context.handlers();
+                generate_pop_handlers(handlers, context);
                 context.add_code(format!("ret {} {}", val_type, val));
             },
-            Stmt::SBlock(ref stmts) => {
+            Stmt::SBlock(ref stmts, _) => {
                 context.in_new_scope(|context| stmts.generate(context));
             }
-            Stmt::SIf(ref expr, ref block) => {
+            Stmt::SIf(ref expr, ref block, _) => {
                 let expr_val = expr.generate(context);
                 let if_label = context.next_label();
                 let after_label = context.next_label();
                 context.add_code(format!("br i1 {}, label {}, label {}", expr_val, if_label, after_label));
                 context.add_label(&if_label);
                 context.in_new_scope(|mut context| block.generate(context));
-                context.add_code(format!("br label {}", after_label));
-                context.add_label(&after_label);
+                generate_jump_after(block, &after_label, context);
+                generate_label_after(self, &after_label, context);
             }
-            Stmt::SIfElse(ref expr, ref block1, ref block2) => {
+            Stmt::SIfElse(ref expr, ref block1, ref block2, _) => {
                 let expr_val = expr.generate(context);
                 let if_label = context.next_label();
                 let else_label = context.next_label();
@@ -131,19 +364,13 @@ impl Generator<()> for Stmt {
                 context.add_code(format!("br i1 {}, label {}, label {}", expr_val, if_label, else_label));
                 context.add_label(&if_label);
                 context.in_new_scope(|mut context| block1.generate(context));
-                if !self.check_return(){
-                    context.add_code(format!("br label {}", after_label));
-                }
+                generate_jump_after(block1, &after_label, context);
                 context.add_label(&else_label);
                 context.in_new_scope(|mut context| block2.generate(context));
-                if !self.check_return(){
-                    context.add_code(format!("br label {}", after_label));
-                }
-                if !self.check_return() {
-                    context.add_label(&after_label);
-                }
+                generate_jump_after(block2, &after_label, context);
+                generate_label_after(self, &after_label, context);
             },
-            Stmt::SWhile(ref expr, ref block) => {
+            Stmt::SWhile(ref expr, ref block, _) => {
                 let while_label = context.next_label();
                 let body_label = context.next_label();
                 let after_label = context.next_label();
@@ -152,15 +379,198 @@ impl Generator<()> for Stmt {
                 let expr_val = expr.generate(context);
                 context.add_code(format!("br i1 {}, label {}, label {}", expr_val, body_label, after_label));
                 context.add_label(&body_label);
-                context.in_new_scope(|mut context| block.generate(context));
-                context.add_code(format!("br label {}", while_label));
-                context.add_label(&after_label);
+                context.in_new_loop(while_label, after_label, |mut context| block.generate(context));
+                generate_jump_after(block, &while_label, context);
+                generate_label_after(self, &after_label, context);
+            },
+            Stmt::SFor(ref init, ref cond, ref step, ref block, _) => {
+                context.in_new_scope(|context| {
+                    init.generate(context);
+                    let cond_label = context.next_label();
+                    let body_label = context.next_label();
+                    let step_label = context.next_label();
+                    let after_label = context.next_label();
+                    context.add_code(format!("br label {}", cond_label));
+                    context.add_label(&cond_label);
+                    let cond_val = cond.generate(context);
+                    context.add_code(format!("br i1 {}, label {}, label {}", cond_val, body_label, after_label));
+                    context.add_label(&body_label);
+                    context.in_new_loop(step_label, after_label, |context| block.generate(context));
+                    generate_jump_after(block, &step_label, context);
+                    context.add_label(&step_label);
+                    step.generate(context);
+                    context.add_code(format!("br label {}", cond_label));
+                    generate_label_after(self, &after_label, context);
+                });
+            },
+            Stmt::SBreak(_) => {
+                let handlers = context.loop_handlers();
+                generate_pop_handlers(handlers, context);
+                let break_label = context.break_label();
+                context.add_code(format!("br label {}", break_label));
+            },
+            Stmt::SContinue(_) => {
+                let handlers = context.loop_handlers();
+                generate_pop_handlers(handlers, context);
+                let continue_label = context.continue_label();
+                context.add_code(format!("br label {}", continue_label));
+            },
+            Stmt::SForEach(ref elem_type, ref var, ref array, ref block, _) => {
+                let array_type = Type::TArray(Box::new(elem_type.clone()));
+                let array = array.generate(context);
+                let length = generate_length(&array, &array_type, context);
+                context.in_new_scope(|context| {
+                    let elem_type_llvm = elem_type.to_llvm();
+                    let var_reg = context.add(var, elem_type);
+                    context.add_code(format!("{} = alloca {}", var_reg, elem_type_llvm));
+                    let counter = generate_assign(context, format!("alloca i32"));
+                    context.add_code(format!("store i32 0, i32* {}", counter));
+
+                    let cond_label = context.next_label();
+                    let body_label = context.next_label();
+                    let step_label = context.next_label();
+                    let after_label = context.next_label();
+                    context.add_code(format!("br label {}", cond_label));
+                    context.add_label(&cond_label);
+                    let index = generate_assign(context, format!("load i32, i32* {}", counter));
+                    let in_range = generate_assign(context, format!("icmp slt i32 {}, {}", index, length));
+                    context.add_code(format!("br i1 {}, label {}, label {}", in_range, body_label, after_label));
+
+                    context.add_label(&body_label);
+                    let data = generate_data_ptr(&array, &array_type, context);
+                    let ptr = generate_assign(context,
+                                              format!("getelementptr {}, {}* {}, i32 {}", elem_type_llvm, elem_type_llvm, data, index));
+                    let val = generate_assign(context, format!("load {}, {}* {}", elem_type_llvm, elem_type_llvm, ptr));
+                    store_var(var, &Val::Register(val), &elem_type_llvm, context);
+                    context.in_new_loop(step_label, after_label, |context| block.generate(context));
+                    generate_jump_after(block, &step_label, context);
+                    context.add_label(&step_label);
+                    let next = generate_assign(context, format!("add i32 {}, 1", index));
+                    context.add_code(format!("store i32 {}, i32* {}", next, counter));
+                    context.add_code(format!("br label {}", cond_label));
+                    context.add_label(&after_label);
+                });
+            },
+            Stmt::SMatch(ref expr, ref cases, _) => generate_match(expr, cases, Cfg::completes(self), context),
+            Stmt::SThrow(ref expr, _) => {
+                let val = expr.generate(context);
+                context.add_code(format!("call void @.throw(i8* {}) noreturn", val));
+                context.add_code(format!("unreachable"));
             },
-            Stmt::Empty => (),
+            Stmt::STry(ref block, ref arg, ref handler, _) => generate_try(block, arg, handler, Cfg::completes(self), context),
+            Stmt::Empty(_) => (),
         }
     }
 }
 
+// Jumps to the label unless control never reaches the end of the statement
+fn generate_jump_after(stmt: &Stmt, label: &Register, context: &mut CGContext) {
+    if Cfg::completes(stmt) {
+        context.add_code(format!("br label {}", label));
+    }
+}
+
+// Starts the block following the statement, which has no predecessors if the statement never completes
+fn generate_label_after(stmt: &Stmt, label: &Register, context: &mut CGContext) {
+    context.add_label(label);
+    if !Cfg::completes(stmt) {
+        context.add_code(format!("unreachable"));
+    }
+}
+
+// Jumps out of try blocks have to remove the handlers they installed
+fn generate_pop_handlers(count: usize, context: &mut CGContext) {
+    if count > 0 {
+        context.add_code(format!("call void @.pop_handlers(i32 {})", count));
+    }
+}
+
+// setjmp returns again with a nonzero value when an exception is thrown inside the try block
+fn generate_try(block: &Stmt, arg: &Arg, handler: &Stmt, completes: bool, context: &mut CGContext) {
+    let env = generate_assign(context, format!("call i8* @.push_handler()"));
+    let thrown = generate_assign(context, format!("call i32 @_setjmp(i8* {}) returns_twice", env));
+    let caught = generate_assign(context, format!("icmp ne i32 {}, 0", thrown));
+    let try_label = context.next_label();
+    let catch_label = context.next_label();
+    let after_label = context.next_label();
+    context.add_code(format!("br i1 {}, label {}, label {}", caught, catch_label, try_label));
+    context.add_label(&try_label);
+    context.in_try_block(|context| block.generate(context));
+    if Cfg::completes(block) {
+        generate_pop_handlers(1, context);
+        context.add_code(format!("br label {}", after_label));
+    }
+    context.add_label(&catch_label);
+    context.in_new_scope(|context| {
+        let Arg(ref t, ref id, _) = *arg;
+        let message = generate_assign(context, format!("call i8* @.catch()"));
+        let reg = context.add(id, t);
+        context.add_code(format!("{} = alloca {}", reg, t.to_llvm()));
+        store_var(id, &Val::Register(message), &t.to_llvm(), context);
+        handler.generate(context);
+    });
+    generate_jump_after(handler, &after_label, context);
+    context.add_label(&after_label);
+    if !completes {
+        context.add_code(format!("unreachable"));
+    }
+}
+
+// The arm is chosen by the tag, values not matched by any variant arm go to the wildcard arm
+fn generate_match(expr: &Expr, cases: &Vec<Case>, completes: bool, context: &mut CGContext) {
+    let value = expr.generate(context);
+    generate_null_check(&value, &expr.get_type(context), context);
+    let tag_ptr = generate_assign(context, format!("bitcast i8* {} to i32*", value));
+    let tag = generate_assign(context, format!("load i32, i32* {}", tag_ptr));
+    let mut labels = vec![];
+    for _ in cases {
+        labels.push(context.next_label());
+    }
+    let after_label = context.next_label();
+    let mut default_label = after_label;
+    let mut destinations = vec![];
+    for (&Case(ref pattern, _), label) in cases.iter().zip(&labels) {
+        match *pattern {
+            Pattern::PVariant(ref variant, _, _) =>
+                destinations.push(format!("i32 {}, label {}", context.get_variant(variant).1, label)),
+            Pattern::PWildcard(_) => default_label = *label,
+        }
+    }
+    context.add_code(format!("switch i32 {}, label {} [ {} ]", tag, default_label, destinations.join(" ")));
+    for (&Case(ref pattern, ref stmt), label) in cases.iter().zip(&labels) {
+        context.add_label(label);
+        context.in_new_scope(|context| {
+            if let Pattern::PVariant(ref variant, ref bindings, _) = *pattern {
+                generate_bindings(&value, variant, bindings, context);
+            }
+            stmt.generate(context);
+        });
+        generate_jump_after(stmt, &after_label, context);
+    }
+    context.add_label(&after_label);
+    if !completes {
+        context.add_code(format!("unreachable"));
+    }
+}
+
+// Fields of the matched variant are copied into local variables
+fn generate_bindings(value: &Val, variant: &String, bindings: &Vec<String>, context: &mut CGContext) {
+    let (enum_name, _, fields) = context.get_variant(variant);
+    let struct_type = variant_struct(&enum_name, variant);
+    let cast = generate_assign(context, format!("bitcast i8* {} to {}*", value, struct_type));
+    for (i, (id, field_type)) in bindings.iter().zip(fields).enumerate() {
+        if id == "_" {
+            continue;
+        }
+        let llvm_type = field_type.to_llvm();
+        let ptr = generate_assign(context, format!("getelementptr {}, {}* {}, i32 0, i32 {}", struct_type, struct_type, cast, i + 1));
+        let val = generate_assign(context, format!("load {}, {}* {}", llvm_type, llvm_type, ptr));
+        let reg = context.add(id, &field_type);
+        context.add_code(format!("{} = alloca {}", reg, llvm_type));
+        store_var(id, &Val::Register(val), &llvm_type, context);
+    }
+}
+
 fn generate_assign(context: &mut CGContext, rhs: String) -> Register {
     let reg = context.next_register();
     context.add_code(format!("{} = {}", reg, rhs));
@@ -169,29 +579,114 @@ fn generate_assign(context: &mut CGContext, rhs: String) -> Register {
 
 fn manipulate_variable(var: &String, operation: String, context: &mut CGContext) {
     let ptr = read_var(var, context);
-    let var_type = context.get_type(var).to_llvm();
+    let var_type = var_type(var, context).to_llvm();
     let val = generate_assign(context, format!("{}, {}", operation, ptr));
     store_var(var, &Val::Register(val), &var_type, context)
 }
 
+// The variable is read before the right-hand side is evaluated, like in var = var op expr
+fn generate_compound_assignment(var: &String, op: BinOp, expr: &Expr, context: &mut CGContext) {
+    let var_type = var_type(var, context);
+    let lhs = read_var(var, context);
+    let rhs = expr.generate(context);
+    let val = match var_type {
+        Type::TString => generate_assign(context, format!("call i8* @.concat(i8* {}, i8* {})", lhs, rhs)),
+        _ => generate_assign(context, format!("{} {} {}, {}", op.to_llvm(&var_type), var_type.to_llvm(), lhs, rhs)),
+    };
+    store_var(var, &Val::Register(val), &var_type.to_llvm(), context)
+}
+
 fn read_var(var: &String, context: &mut CGContext) -> Val {
-    let var_reg = context.get_register(var);
-    let var_type = context.get_type(var).to_llvm();
+    if is_function_name(var, context) {
+        context.add_function_value(var);
+        return Val::Register(generate_assign(context, format!("bitcast %closure* @.closure.{} to i8*", var)));
+    }
+    if is_variant_name(var, context) {
+        return generate_new_variant(var, &vec![], context);
+    }
+    let var_type = var_type(var, context).to_llvm();
+    let var_reg = var_ptr(var, context);
     Val::Register(generate_assign(context, format!("load {}, {}* {}", var_type, var_type, var_reg)))
 }
 
 fn store_var(var: &String, val: &Val, var_type: &String, context: &mut CGContext) {
-    let reg = context.get_register(var);
+    let reg = var_ptr(var, context);
     context.add_code(format!("store {} {}, {}* {}", var_type, val.clone(), var_type, reg))
 }
 
+// Identifiers that are not local variables refer to fields of self, then to globals
+fn var_ptr(var: &String, context: &mut CGContext) -> Val {
+    if context.has_var(var) {
+        Val::Register(context.get_register(var))
+    } else if let Some(class) = self_field(var, context) {
+        let object = read_var(&String::from("self"), context);
+        Val::Register(generate_object_field_ptr(&object, &class, var, context))
+    } else {
+        Val::Global(var.clone())
+    }
+}
+
+fn var_type(var: &String, context: &CGContext) -> Type {
+    if context.has_var(var) {
+        context.get_type(var)
+    } else if let Some(class) = self_field(var, context) {
+        context.get_field(&class, var).1
+    } else if context.has_function(var) {
+        context.get_function_type(var)
+    } else if context.has_variant(var) {
+        Type::TClass(context.get_variant(var).0)
+    } else {
+        context.get_global_type(var)
+    }
+}
+
+// Top level functions used as values are referred to by their closure records
+fn is_function_name(var: &String, context: &CGContext) -> bool {
+    !context.has_var(var) && self_field(var, context).is_none() && context.has_function(var)
+}
+
+fn self_field(var: &String, context: &CGContext) -> Option<String> {
+    context.current_class().and_then(|class| if context.has_field(&class, var) { Some(class) } else { None })
+}
+
+fn is_variant_name(var: &String, context: &CGContext) -> bool {
+    !context.has_var(var) && self_field(var, context).is_none() && context.has_variant(var)
+}
+
+fn is_generic_name(var: &String, context: &CGContext) -> bool {
+    !context.has_var(var) && self_field(var, context).is_none() && context.has_generic(var)
+}
+
+// Mangled name, type arguments and return type of the instance of a generic function used by a call
+fn generic_instance(fun: &String, args: &Vec<Expr>, context: &CGContext) -> (String, Vec<Type>, Type) {
+    let &(ref type_params, ref def) = context.get_generic(fun);
+    match *def {
+        Def::DFun(ref ret_type, _, ref params, _, _) => {
+            let param_types = params.iter().map(|param| param.0.clone()).collect();
+            let arg_types = args.iter().map(|arg| arg.get_type(context)).collect();
+            let bindings = infer_type_args(type_params, &param_types, &arg_types);
+            let type_args = type_params.iter().map(|type_param| bindings[type_param].clone()).collect();
+            (mangle(fun, &type_args), type_args, ret_type.substitute(&bindings))
+        },
+        _ => unreachable!(),
+    }
+}
+
+// Local variables and fields holding functions hide methods of the same name
+fn self_method(fun: &String, context: &CGContext) -> Option<String> {
+    if context.has_var(fun) || self_field(fun, context).is_some() {
+        return None;
+    }
+    context.current_class().and_then(|class| context.get_method(&class, fun).map(|_| class))
+}
+
 fn generate_item(item_type: &Type, item: &Item, context: &mut CGContext) {
     let val = match *item {
-        Item::Init(_, ref expr) => {
+        Item::Init(_, ref expr, _) => {
             expr.generate(context)
         },
-        Item::NoInit(_) => {
-           item_type.default_value().generate(context)
+        Item::NoInit(_, _) => {
+           item_type.default_value(context)
         },
     };
     let id = item.get_id();
@@ -200,12 +695,228 @@ fn generate_item(item_type: &Type, item: &Item, context: &mut CGContext) {
     store_var(&id, &val, &item_type.to_llvm(), context)
 }
 
+fn generate_new_array(elem_type: &Type, size: &Expr, context: &mut CGContext) -> Val {
+    let size = size.generate(context);
+    let array = match *elem_type {
+        Type::TString => generate_assign(context, format!("call i8* @.new_string_array(i32 {})", size)),
+        _ => {
+            let elem_size = generate_size_of(&elem_type.to_llvm(), context);
+            generate_assign(context, format!("call i8* @.new_array(i32 {}, i32 {})", size, elem_size))
+        },
+    };
+    let array_type = Type::TArray(Box::new(elem_type.clone())).to_llvm();
+    Val::Register(generate_assign(context, format!("bitcast i8* {} to {}", array, array_type)))
+}
+
+fn generate_new_object(class: &String, context: &mut CGContext) -> Val {
+    let struct_type = Type::TClass(class.clone()).class_struct();
+    let size = generate_size_of(&struct_type, context);
+    let object = generate_assign(context, format!("call i8* @.new_object(i32 {})", size));
+    let cast = generate_assign(context, format!("bitcast i8* {} to {}*", object, struct_type));
+    let vtable = generate_assign(context,
+                                 format!("getelementptr {}, {}* {}, i32 0, i32 0", struct_type, struct_type, cast));
+    context.add_code(format!("store %vtable.{}* @vtable.{}, %vtable.{}** {}", class, class, class, vtable));
+    for (i, Field(field_type, _, _)) in context.get_class(class).fields.into_iter().enumerate() {
+        let val = field_type.default_value(context);
+        let field_type = field_type.to_llvm();
+        let ptr = generate_assign(context,
+                                  format!("getelementptr {}, {}* {}, i32 0, i32 {}", struct_type, struct_type, cast, i + 1));
+        context.add_code(format!("store {} {}, {}* {}", field_type, val, field_type, ptr));
+    }
+    Val::Register(object)
+}
+
+fn generate_new_variant(variant: &String, args: &Vec<Expr>, context: &mut CGContext) -> Val {
+    let (enum_name, tag, _) = context.get_variant(variant);
+    let struct_type = variant_struct(&enum_name, variant);
+    let llvm_args = args_to_llvm(args, context);
+    let size = generate_size_of(&struct_type, context);
+    let object = generate_assign(context, format!("call i8* @.new_object(i32 {})", size));
+    let cast = generate_assign(context, format!("bitcast i8* {} to {}*", object, struct_type));
+    let tag_ptr = generate_assign(context, format!("getelementptr {}, {}* {}, i32 0, i32 0", struct_type, struct_type, cast));
+    context.add_code(format!("store i32 {}, i32* {}", tag, tag_ptr));
+    for (i, (val, arg_type)) in llvm_args.into_iter().enumerate() {
+        let arg_type = arg_type.to_llvm();
+        let ptr = generate_assign(context,
+                                  format!("getelementptr {}, {}* {}, i32 0, i32 {}", struct_type, struct_type, cast, i + 1));
+        context.add_code(format!("store {} {}, {}* {}", arg_type, val, arg_type, ptr));
+    }
+    Val::Register(object)
+}
+
+fn generate_field_ptr(object: &Expr, field: &String, context: &mut CGContext) -> Register {
+    let object_type = object.get_type(context);
+    let class = object_type.class_name();
+    let object = object.generate(context);
+    generate_null_check(&object, &object_type, context);
+    generate_object_field_ptr(&object, &class, field, context)
+}
+
+fn generate_object_field_ptr(object: &Val, class: &String, field: &String, context: &mut CGContext) -> Register {
+    let struct_type = Type::TClass(class.clone()).class_struct();
+    let (index, _) = context.get_field(class, field);
+    let cast = generate_assign(context, format!("bitcast i8* {} to {}*", object, struct_type));
+    generate_assign(context, format!("getelementptr {}, {}* {}, i32 0, i32 {}", struct_type, struct_type, cast, index + 1))
+}
+
+fn generate_method_call(object: Val, class: &String, method: &String, args: &Vec<Expr>, context: &mut CGContext) -> Val {
+    let (index, method_type) = context.get_method(class, method).unwrap();
+    let struct_type = Type::TClass(class.clone()).class_struct();
+    let vtable_type = format!("%vtable.{}", class);
+    let method_llvm_type = method_to_llvm(&method_type);
+
+    let cast = generate_assign(context, format!("bitcast i8* {} to {}*", object, struct_type));
+    let vtable_ptr = generate_assign(context,
+                                     format!("getelementptr {}, {}* {}, i32 0, i32 0", struct_type, struct_type, cast));
+    let vtable = generate_assign(context, format!("load {}*, {}** {}", vtable_type, vtable_type, vtable_ptr));
+    let method_ptr = generate_assign(context,
+                                     format!("getelementptr {}, {}* {}, i32 0, i32 {}", vtable_type, vtable_type, vtable, index));
+    let method = generate_assign(context, format!("load {}, {}* {}", method_llvm_type, method_llvm_type, method_ptr));
+
+    let mut llvm_args = vec![(object, Type::TClass(class.clone()))];
+    llvm_args.extend(args_to_llvm(args, context));
+    generate_call(&format!("{}", method), &method_type.ret_type(), llvm_args, context)
+}
+
+fn generate_call(fun: &String, ret_type: &Type, args: Vec<(Val, Type)>, context: &mut CGContext) -> Val {
+    let args = args.into_iter().map(|(val, arg_type)| format!("{} {}", arg_type.to_llvm(), val)).collect();
+    generate_llvm_call(fun, ret_type, args, context)
+}
+
+fn generate_llvm_call(fun: &String, ret_type: &Type, args: Vec<String>, context: &mut CGContext) -> Val {
+    let res = context.next_register();
+    let call = match *ret_type {
+        Type::TVoid => format!("call {} {}({})", ret_type.to_llvm(), fun, args.join(", ")),
+        _ => format!("{} = call {} {}({})", res, ret_type.to_llvm(), fun, args.join(", ")),
+    };
+    context.add_code(call);
+    Val::Register(res)
+}
+
+fn generate_closure_call(closure: &Val, fun_type: &Type, args: &Vec<Expr>, context: &mut CGContext) -> Val {
+    let cast = generate_assign(context, format!("bitcast i8* {} to %closure*", closure));
+    let code_ptr = generate_assign(context, format!("getelementptr %closure, %closure* {}, i32 0, i32 0", cast));
+    let code = generate_assign(context, format!("load i8*, i8** {}", code_ptr));
+    let env_ptr = generate_assign(context, format!("getelementptr %closure, %closure* {}, i32 0, i32 1", cast));
+    let env = generate_assign(context, format!("load i8*, i8** {}", env_ptr));
+    let fun = generate_assign(context, format!("bitcast i8* {} to {}", code, method_to_llvm(fun_type)));
+
+    let mut llvm_args = vec![format!("i8* {}", env)];
+    llvm_args.extend(args_to_llvm(args, context).into_iter().map(|(val, arg_type)| format!("{} {}", arg_type.to_llvm(), val)));
+    generate_llvm_call(&format!("{}", fun), &fun_type.ret_type(), llvm_args, context)
+}
+
+fn generate_closure(args: &Vec<Arg>, body: &Expr, captures: &Captures, context: &mut CGContext) -> Val {
+    let fun_type = lambda_type(args, body, context);
+    let captures = captures.borrow().iter().map(|id| (id.clone(), context.get_type(id))).collect();
+    let env = generate_environment(&captures, context);
+    let name = context.add_lambda(captures, args, fun_type.ret_type(), body);
+
+    let size = generate_size_of(&String::from("%closure"), context);
+    let closure = generate_assign(context, format!("call i8* @.new_object(i32 {})", size));
+    let cast = generate_assign(context, format!("bitcast i8* {} to %closure*", closure));
+    let code_ptr = generate_assign(context, format!("getelementptr %closure, %closure* {}, i32 0, i32 0", cast));
+    context.add_code(format!("store i8* bitcast ({} @{} to i8*), i8** {}", method_to_llvm(&fun_type), name, code_ptr));
+    let env_ptr = generate_assign(context, format!("getelementptr %closure, %closure* {}, i32 0, i32 1", cast));
+    context.add_code(format!("store i8* {}, i8** {}", env, env_ptr));
+    Val::Register(closure)
+}
+
+// Captured variables are copied into the environment when the closure is created
+fn generate_environment(captures: &Vec<(String, Type)>, context: &mut CGContext) -> Val {
+    if captures.is_empty() {
+        return Val::Const(Const::Null);
+    }
+    let env_type = environment_type(captures);
+    let size = generate_size_of(&env_type, context);
+    let env = generate_assign(context, format!("call i8* @.new_object(i32 {})", size));
+    let cast = generate_assign(context, format!("bitcast i8* {} to {}*", env, env_type));
+    for (i, &(ref id, ref var_type)) in captures.iter().enumerate() {
+        let val = read_var(id, context);
+        let var_type = var_type.to_llvm();
+        let ptr = generate_assign(context, format!("getelementptr {}, {}* {}, i32 0, i32 {}", env_type, env_type, cast, i));
+        context.add_code(format!("store {} {}, {}* {}", var_type, val, var_type, ptr));
+    }
+    Val::Register(env)
+}
+
+fn environment_type(captures: &Vec<(String, Type)>) -> String {
+    let types = captures.iter().map(|capture| capture.1.to_llvm()).collect::<Vec<String>>();
+    format!("{{ {} }}", types.join(", "))
+}
+
+fn lambda_type(args: &Vec<Arg>, body: &Expr, context: &CGContext) -> Type {
+    let mut scope = context.scope();
+    for arg in args {
+        scope.add(&arg.1, &arg.0);
+    }
+    Type::TFunc(Box::new(body.get_type(&scope)), args.iter().map(|arg| arg.0.clone()).collect())
+}
+
+fn generate_size_of(llvm_type: &String, context: &mut CGContext) -> Register {
+    let end = generate_assign(context, format!("getelementptr {}, {}* null, i32 1", llvm_type, llvm_type));
+    generate_assign(context, format!("ptrtoint {}* {} to i32", llvm_type, end))
+}
+
+fn generate_length(array: &Val, array_type: &Type, context: &mut CGContext) -> Register {
+    let struct_type = array_type.array_struct();
+    generate_null_check(array, array_type, context);
+    let ptr = generate_assign(context,
+                              format!("getelementptr {}, {}* {}, i32 0, i32 0", struct_type, struct_type, array));
+    generate_assign(context, format!("load i32, i32* {}", ptr))
+}
+
+fn generate_data_ptr(array: &Val, array_type: &Type, context: &mut CGContext) -> Register {
+    let struct_type = array_type.array_struct();
+    let data_type = array_type.array_elem().to_llvm();
+    let ptr = generate_assign(context,
+                              format!("getelementptr {}, {}* {}, i32 0, i32 1", struct_type, struct_type, array));
+    generate_assign(context, format!("load {}*, {}** {}", data_type, data_type, ptr))
+}
+
+fn generate_element_ptr(array: &Expr, index: &Expr, context: &mut CGContext) -> Register {
+    let array_type = array.get_type(context);
+    let elem_type = array_type.array_elem().to_llvm();
+    let array = array.generate(context);
+    let index = index.generate(context);
+    let length = generate_length(&array, &array_type, context);
+    generate_bounds_check(&index, &length, context);
+    let data = generate_data_ptr(&array, &array_type, context);
+    generate_assign(context, format!("getelementptr {}, {}* {}, i32 {}", elem_type, elem_type, data, index))
+}
+
+fn generate_bounds_check(index: &Val, length: &Register, context: &mut CGContext) {
+    let in_bounds = generate_assign(context, format!("icmp ult i32 {}, {}", index, length));
+    generate_runtime_check(&in_bounds, context)
+}
+
+fn generate_null_check(pointer: &Val, pointer_type: &Type, context: &mut CGContext) {
+    let not_null = generate_assign(context, format!("icmp ne {} {}, null", pointer_type.to_llvm(), pointer));
+    generate_runtime_check(&not_null, context)
+}
+
+// Calls error() unless the condition holds
+fn generate_runtime_check(cond: &Register, context: &mut CGContext) {
+    let ok_label = context.next_label();
+    let error_label = context.next_label();
+    context.add_code(format!("br i1 {}, label {}, label {}", cond, ok_label, error_label));
+    context.add_label(&error_label);
+    context.add_code(format!("call void @error()"));
+    context.add_code(format!("unreachable"));
+    context.add_label(&ok_label);
+}
+
 impl Type {
-    fn default_value(&self) -> Expr {
+    fn default_value(&self, context: &mut CGContext) -> Val {
         match *self {
-            Type::TBool => Expr::EBoolLit(false),
-            Type::TInt => Expr::EIntLit(0),
-            Type::TString => Expr::EStringLit(String::new()),
+            Type::TBool => Val::Const(Const::BConst(false)),
+            Type::TInt => Val::Const(Const::IConst(0)),
+            Type::TLong => Val::Const(Const::LConst(0)),
+            Type::TDouble => Val::Const(Const::DConst(0.0)),
+            Type::TString => Expr::EStringLit(String::new(), Span(0, 0)).generate(context),
+            Type::TArray(_) |
+            Type::TClass(_) |
+            Type::TFunc(_, _) => Val::Const(Const::Null),
             _ => unreachable!(),
         }
     }
@@ -213,50 +924,129 @@ impl Type {
     pub fn to_llvm(&self) -> String {
         String::from(match *self {
             Type::TInt => "i32",
+            Type::TLong => "i64",
+            Type::TDouble => "double",
             Type::TBool => "i1",
             Type::TString => "i8*",
             Type::TVoid => "void",
+            Type::TArray(_) => return format!("{}*", self.array_struct()),
+            // Objects are passed around as raw pointers, so that an instance of a subclass
+            // can be used wherever its superclass is expected without any casts
+            Type::TClass(_) => "i8*",
+            // Function values are pointers to closure records
+            Type::TFunc(_, _) => "i8*",
             _ => unreachable!()
         })
     }
+
+    fn array_struct(&self) -> String {
+        format!("{{ i32, {}* }}", self.array_elem().to_llvm())
+    }
+
+    fn class_struct(&self) -> String {
+        format!("%class.{}", self.class_name())
+    }
+
+    fn class_name(&self) -> String {
+        match *self {
+            Type::TClass(ref name) => name.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn ret_type(&self) -> Type {
+        match *self {
+            Type::TFunc(ref ret_type, _) => *ret_type.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn array_elem(&self) -> Type {
+        match *self {
+            Type::TArray(ref elem_type) => *elem_type.clone(),
+            _ => unreachable!(),
+        }
+    }
 }
 
 
 impl Generator<Val> for Expr {
     fn generate(&self, context: &mut CGContext) -> Val {
         match *self {
-            Expr::EIntLit(x) => Val::Const(Const::IConst(x)),
-            Expr::EBoolLit(b) => Val::Const(Const::BConst(b)),
-            Expr::EVar(ref id) => read_var(id, context),
-            Expr::ENeg(ref expr) => {
+            Expr::EIntLit(x, _) => Val::Const(Const::IConst(x)),
+            Expr::ELongLit(x, _) => Val::Const(Const::LConst(x)),
+            Expr::EDoubleLit(x, _) => Val::Const(Const::DConst(x)),
+            Expr::EBoolLit(b, _) => Val::Const(Const::BConst(b)),
+            Expr::EVar(ref id, _) => read_var(id, context),
+            Expr::ENeg(ref expr, _) => {
+                let t = expr.get_type(context);
                 let e = expr.generate(context);
-                Val::Register(generate_assign(context, format!("sub i32 0, {}", e)))
+                match t {
+                    Type::TDouble => Val::Register(generate_assign(context, format!("fsub double -0.0, {}", e))),
+                    _ => Val::Register(generate_assign(context, format!("sub {} 0, {}", t.to_llvm(), e))),
+                }
             },
-            Expr::ENot(ref expr) => {
+            Expr::ENot(ref expr, _) => {
                 let e = expr.generate(context);
                 Val::Register(generate_assign(context, format!("sub i1 1, {}", e)))
             },
-            Expr::EOp(ref lhs, ref op, ref rhs) => Val::Register(generate_op(lhs, op, rhs, context)),
-            Expr::EApp(ref s, ref args) => {
-                let llvm_args = args_to_llvm(args, context);
-                let ret_type = context.get_type(s);
-                let res = context.next_register();
-                let mut call = match ret_type {
-                    Type::TVoid => format!("call {} @{}(", ret_type.to_llvm(), s),
-                    _ => format!("{} = call {} @{}(", res, ret_type.to_llvm(), s),
-
-                };
-                for (i, (val, arg_type)) in llvm_args.into_iter().enumerate() {
-                    if i > 0 {
-                        call = format!("{}, ", call);
-                    }
-                    call = format!("{}{} {}", call, arg_type.to_llvm(), val);
-                }
-                context.add_code(format!("{})", call));
-                Val::Register(res)
+            Expr::EBitNot(ref expr, _) => {
+                let e = expr.generate(context);
+                Val::Register(generate_assign(context, format!("xor i32 {}, -1", e)))
             },
-            Expr::EPredef(ref predef) => predef.generate(context),
-            Expr::EStringLit(ref s) => {
+            Expr::EOp(ref lhs, ref op, ref rhs, _) => Val::Register(generate_op(lhs, op, rhs, context)),
+            Expr::EApp(ref s, ref args, _) => match self_method(s, context) {
+                Some(class) => {
+                    let object = read_var(&String::from("self"), context);
+                    generate_method_call(object, &class, s, args, context)
+                },
+                None if is_variant_name(s, context) => generate_new_variant(s, args, context),
+                None if is_generic_name(s, context) => {
+                    let (name, type_args, ret_type) = generic_instance(s, args, context);
+                    context.add_instance(&name, s, type_args);
+                    let llvm_args = args_to_llvm(args, context);
+                    generate_call(&format!("@{}", name), &ret_type, llvm_args, context)
+                },
+                None if is_function_name(s, context) => {
+                    let llvm_args = args_to_llvm(args, context);
+                    let ret_type = context.get_function_type(s).ret_type();
+                    generate_call(&format!("@{}", s), &ret_type, llvm_args, context)
+                },
+                None => {
+                    let closure = read_var(s, context);
+                    let fun_type = var_type(s, context);
+                    generate_closure_call(&closure, &fun_type, args, context)
+                },
+            },
+            Expr::EMethodCall(ref object, ref method, ref args, _) => {
+                let object_type = object.get_type(context);
+                let class = object_type.class_name();
+                let object = object.generate(context);
+                generate_null_check(&object, &object_type, context);
+                generate_method_call(object, &class, method, args, context)
+            },
+            Expr::EPredef(ref predef, _) => predef.generate(context),
+            Expr::ENewArray(ref elem_type, ref size, _) => generate_new_array(elem_type, size, context),
+            Expr::EIndex(ref array, ref index, _) => {
+                let elem_type = self.get_type(context).to_llvm();
+                let ptr = generate_element_ptr(array, index, context);
+                Val::Register(generate_assign(context, format!("load {}, {}* {}", elem_type, elem_type, ptr)))
+            },
+            Expr::EField(ref expr, ref field, _) => match expr.get_type(context) {
+                Type::TClass(_) => {
+                    let field_type = self.get_type(context).to_llvm();
+                    let ptr = generate_field_ptr(expr, field, context);
+                    Val::Register(generate_assign(context, format!("load {}, {}* {}", field_type, field_type, ptr)))
+                },
+                array_type => {
+                    let array = expr.generate(context);
+                    Val::Register(generate_length(&array, &array_type, context))
+                },
+            },
+            Expr::ENew(ref class, _) => generate_new_object(class, context),
+            Expr::ENull(_, _) => Val::Const(Const::Null),
+            Expr::ELambda(ref args, ref body, ref captures, _) => generate_closure(args, body, captures, context),
+            Expr::EStringLit(ref s, _) => {
                 let c = Val::Const(context.get_const(s));
                 Val::Register(generate_assign(context,
                                 format!("getelementptr [{} x i8], [{} x i8]* {}, i64 0, i64 0", s.len() + 1, s.len() + 1, c)))
@@ -275,6 +1065,7 @@ fn generate_op(lhs: &Expr, op: &BinOp, rhs: &Expr, context: &mut CGContext) -> R
 
             context.add_label(&lhs_label);
             let lhs = lhs.generate(context);
+            let lhs_end_label = context.last_label();
             context.add_code(format!("br i1 {}, label {}, label {}", lhs, rhs_label, end_label));
 
             context.add_label(&rhs_label);
@@ -283,7 +1074,7 @@ fn generate_op(lhs: &Expr, op: &BinOp, rhs: &Expr, context: &mut CGContext) -> R
 
             let last_label = context.last_label();
             context.add_label(&end_label);
-            generate_assign(context, format!("phi i1 [0, {}], [{}, {}]", lhs_label, rhs, last_label))
+            generate_assign(context, format!("phi i1 [0, {}], [{}, {}]", lhs_end_label, rhs, last_label))
         },
         BinOp::Or => {
             let lhs_label = context.next_label();
@@ -293,6 +1084,7 @@ fn generate_op(lhs: &Expr, op: &BinOp, rhs: &Expr, context: &mut CGContext) -> R
 
             context.add_label(&lhs_label);
             let lhs = lhs.generate(context);
+            let lhs_end_label = context.last_label();
             context.add_code(format!("br i1 {}, label {}, label {}", lhs, end_label, rhs_label));
 
             context.add_label(&rhs_label);
@@ -301,7 +1093,7 @@ fn generate_op(lhs: &Expr, op: &BinOp, rhs: &Expr, context: &mut CGContext) -> R
 
             let last_label = context.last_label();
             context.add_label(&end_label);
-            generate_assign(context, format!("phi i1 [1, {}], [{}, {}]", lhs_label, rhs, last_label))
+            generate_assign(context, format!("phi i1 [1, {}], [{}, {}]", lhs_end_label, rhs, last_label))
         },
         BinOp::Add => {
             let t = lhs.get_type(context);
@@ -309,14 +1101,28 @@ fn generate_op(lhs: &Expr, op: &BinOp, rhs: &Expr, context: &mut CGContext) -> R
             let rhs = rhs.generate(context);
             match t {
                 Type::TString => generate_assign(context, format!("call i8* @.concat(i8* {}, i8* {})", &lhs, &rhs)),
-                _ => generate_assign(context, format!("{} i32 {}, {}", op.to_llvm(), &lhs, &rhs)),
+                _ => generate_assign(context, format!("{} {} {}, {}", op.to_llvm(&t), t.to_llvm(), &lhs, &rhs)),
             }
         },
+        // Shift counts are taken modulo 32, larger ones would give an undefined result
+        BinOp::Shl | BinOp::Shr => {
+            let lhs = lhs.generate(context);
+            let rhs = rhs.generate(context);
+            let count = generate_assign(context, format!("and i32 {}, 31", &rhs));
+            generate_assign(context, format!("{} i32 {}, {}", op.to_llvm(&Type::TInt), &lhs, count))
+        },
+        BinOp::EQ | BinOp::NEQ | BinOp::LT | BinOp::LE | BinOp::GT | BinOp::GE
+            if lhs.get_type(context) == Type::TString => {
+            let lhs = lhs.generate(context);
+            let rhs = rhs.generate(context);
+            let cmp = generate_assign(context, format!("call i32 @.compare_strings(i8* {}, i8* {})", &lhs, &rhs));
+            generate_assign(context, format!("{} i32 {}, 0", op.to_llvm(&Type::TInt), cmp))
+        },
         _ => {
-            let t = lhs.get_type(context).to_llvm();
+            let t = lhs.get_type(context);
             let lhs = lhs.generate(context);
             let rhs = rhs.generate(context);
-            generate_assign(context, format!("{} {} {}, {}", op.to_llvm(), t, &lhs, &rhs))
+            generate_assign(context, format!("{} {} {}, {}", op.to_llvm(&t), t.to_llvm(), &lhs, &rhs))
         },
     }
 }
@@ -329,24 +1135,66 @@ impl Generator<Val> for Predef {
                 context.add_code(format!("call void @printInt(i32 {})", arg));
                 Val::Register(context.next_register())
             },
+            Predef::PrintLong(ref e) => {
+                let arg = e.generate(context);
+                context.add_code(format!("call void @printLong(i64 {})", arg));
+                Val::Register(context.next_register())
+            },
+            Predef::PrintDouble(ref e) => {
+                let arg = e.generate(context);
+                context.add_code(format!("call void @printDouble(double {})", arg));
+                Val::Register(context.next_register())
+            },
             Predef::PrintString(ref e) => {
                 let arg = e.generate(context);
                 context.add_code(format!("call void @printString(i8* {})", arg));
                 Val::Register(context.next_register())
             },
             Predef::Error => {
-                context.add_code(format!("call void @error()"));
+                context.add_code(format!("call void @error() noreturn"));
+                context.add_code(format!("unreachable"));
                 Val::Register(context.next_register())
             }
             Predef::ReadInt => Val::Register(generate_assign(context, format!("call i32 @readInt()"))),
+            Predef::ReadDouble => Val::Register(generate_assign(context, format!("call double @readDouble()"))),
             Predef::ReadString => Val::Register(generate_assign(context, format!("call i8* @readString()"))),
+            Predef::Convert(ref t, ref e) => generate_conversion(e, t, context),
         }
     }
 }
 
+// Doubles are truncated towards zero when converted to integers
+fn generate_conversion(expr: &Expr, t: &Type, context: &mut CGContext) -> Val {
+    let from = expr.get_type(context);
+    let val = expr.generate(context);
+    let instruction = match (&from, t) {
+        (&Type::TInt, &Type::TLong) => "sext",
+        (&Type::TLong, &Type::TInt) => "trunc",
+        (&Type::TDouble, &Type::TInt) |
+        (&Type::TDouble, &Type::TLong) => "fptosi",
+        (&Type::TInt, &Type::TDouble) |
+        (&Type::TLong, &Type::TDouble) => "sitofp",
+        _ => return val,
+    };
+    Val::Register(generate_assign(context, format!("{} {} {} to {}", instruction, from.to_llvm(), val, t.to_llvm())))
+}
+
 impl BinOp {
-    fn to_llvm(&self) -> String {
+    // Instruction for operands of the given type
+    // The type checker allows %, bitwise operators and shifts on integers only
+    fn to_llvm(&self, t: &Type) -> String {
+        let double = *t == Type::TDouble;
         String::from(match *self {
+            BinOp::Add if double => "fadd",
+            BinOp::Sub if double => "fsub",
+            BinOp::Mul if double => "fmul",
+            BinOp::Div if double => "fdiv",
+            BinOp::LT if double => "fcmp olt",
+            BinOp::GT if double => "fcmp ogt",
+            BinOp::LE if double => "fcmp ole",
+            BinOp::GE if double => "fcmp oge",
+            BinOp::EQ if double => "fcmp oeq",
+            BinOp::NEQ if double => "fcmp une",
             BinOp::Add => "add",
             BinOp::Sub => "sub",
             BinOp::Mul => "mul",
@@ -358,17 +1206,19 @@ impl BinOp {
             BinOp::GE => "icmp sge",
             BinOp::EQ => "icmp eq",
             BinOp::NEQ => "icmp ne",
-            _ => unimplemented!(),
+            BinOp::BitAnd => "and",
+            BinOp::BitOr => "or",
+            BinOp::BitXor => "xor",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "ashr",
+            BinOp::And | BinOp::Or => unreachable!("&& and || are lowered to branches, not instructions"),
         })
     }
 
     pub fn get_type(&self) -> Option<Type> {
         match *self {
-            BinOp::Sub |
-            BinOp::Mul |
-            BinOp::Div |
-            BinOp::Mod => Some(Type::TInt),
             BinOp::And |
+            BinOp::EQ |
             BinOp::NEQ |
             BinOp::Or |
             BinOp::GE |
@@ -395,15 +1245,36 @@ fn args_to_llvm(args: &Vec<Expr>, context: &mut CGContext) -> Vec<(Val, Type)> {
 impl Expr {
     fn get_type(&self, context: &CGContext) -> Type {
         match *self {
-            Expr::EOp(ref lhs, ref op, _) => op.get_type().unwrap_or(lhs.get_type(context)),
-            Expr::EApp(ref id, _) => context.get_type(id),
-            Expr::EBoolLit(_) |
-            Expr::ENot(_) => Type::TBool,
-            Expr::EIntLit(_) |
-            Expr::ENeg(_) => Type::TInt,
-            Expr::EStringLit(_) => Type::TString,
-            Expr::EPredef(ref predef) => predef.get_type(),
-            Expr::EVar(ref id) => context.get_type(id),
+            Expr::EOp(ref lhs, ref op, _, _) => op.get_type().unwrap_or(lhs.get_type(context)),
+            Expr::EApp(ref id, ref args, _) => match self_method(id, context) {
+                Some(class) => context.get_method(&class, id).unwrap().1.ret_type(),
+                None if is_variant_name(id, context) => Type::TClass(context.get_variant(id).0),
+                None if is_generic_name(id, context) => generic_instance(id, args, context).2,
+                None => var_type(id, context).ret_type(),
+            },
+            Expr::EMethodCall(ref object, ref method, _, _) => {
+                let class = object.get_type(context).class_name();
+                context.get_method(&class, method).unwrap().1.ret_type()
+            },
+            Expr::EBoolLit(_, _) |
+            Expr::ENot(_, _) => Type::TBool,
+            Expr::EBitNot(_, _) => Type::TInt,
+            Expr::EIntLit(_, _) => Type::TInt,
+            Expr::ELongLit(_, _) => Type::TLong,
+            Expr::EDoubleLit(_, _) => Type::TDouble,
+            Expr::ENeg(ref expr, _) => expr.get_type(context),
+            Expr::EStringLit(_, _) => Type::TString,
+            Expr::EPredef(ref predef, _) => predef.get_type(),
+            Expr::EVar(ref id, _) => var_type(id, context),
+            Expr::ENewArray(ref elem_type, _, _) => Type::TArray(Box::new(elem_type.clone())),
+            Expr::EIndex(ref array, _, _) => array.get_type(context).array_elem(),
+            Expr::EField(ref expr, ref field, _) => match expr.get_type(context) {
+                Type::TClass(ref class) => context.get_field(class, field).1,
+                _ => Type::TInt,
+            },
+            Expr::ENew(ref class, _) => Type::TClass(class.clone()),
+            Expr::ENull(ref t, _) => t.clone(),
+            Expr::ELambda(ref args, ref body, _, _) => lambda_type(args, body, context),
         }
     }
 }
\ No newline at end of file
@@ -1,19 +1,47 @@
 use std::collections::HashMap;
-use std::fs::File;
 use std::fmt;
 use std::fmt::Display;
 use std::io;
 use std::io::Write;
-use ast::Type;
+use ast::{Type, Field, Arg, Def, Expr};
 
 type Vars = HashMap<String, Register>;
 type Consts = HashMap<String, Const>;
 type Types = HashMap<String, Type>;
+type Classes = HashMap<String, Class>;
+// Generic function -> (Type parameters, Function)
+type Generics = HashMap<String, (Vec<String>, Def)>;
+// Variant -> (Enum, Tag, Field types)
+type Variants = HashMap<String, (String, i32, Vec<Type>)>;
+// (Mangled name, Generic function, Type arguments)
+type Instance = (String, String, Vec<Type>);
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    // Fields including the inherited ones, in memory order
+    pub fields: Vec<Field>,
+    // (Method name, Implementing class, Method type), in vtable order
+    pub methods: Vec<(String, String, Type)>,
+}
+
+// Body of a lambda, generated as a top level function once the current one is finished
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    pub name: String,
+    pub class: Option<String>,
+    // Captured variables, in the order they are stored in the environment
+    pub captures: Vec<(String, Type)>,
+    pub args: Vec<Arg>,
+    pub ret_type: Type,
+    pub body: Expr,
+}
 
 #[derive(Clone)]
 pub enum Val {
     Const(Const),
     Register(Register),
+    // Address of a global variable
+    Global(String),
 }
 
 impl Display for Val {
@@ -21,6 +49,7 @@ impl Display for Val {
         match *self {
             Val::Const(c) => write!(f, "{}", c),
             Val::Register(r) => write!(f, "{}", r),
+            Val::Global(ref name) => write!(f, "@{}", name),
         }
     }
 }
@@ -28,16 +57,23 @@ impl Display for Val {
 #[derive(Clone, Copy, Debug)]
 pub enum Const {
     IConst(i32),
+    LConst(i64),
+    DConst(f64),
     SConst(i32),
     BConst(bool),
+    Null,
 }
 
 impl Display for Const {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Const::IConst(x) => write!(f, "{}", x),
+            Const::LConst(x) => write!(f, "{}", x),
+            // Hexadecimal form is exact for every value, including infinities and NaN
+            Const::DConst(x) => write!(f, "0x{:016X}", x.to_bits()),
             Const::SConst(x) => write!(f, "@.str{}", x),
             Const::BConst(x) => write!(f, "{}", x as u8),
+            Const::Null => write!(f, "null"),
         }
     }
 }
@@ -78,11 +114,28 @@ pub struct CGContext {
     output: Vec<String>,
     vars: Vars,
     types: Types,
+    globals: Types,
+    functions: Types,
     consts: Consts,
+    classes: Classes,
+    variants: Variants,
+    class: Option<String>,
+    // (Continue label, Break label, Handlers installed outside) of the enclosing loops
+    loops: Vec<(Register, Register, usize)>,
+    // Exception handlers installed by the enclosing try blocks of the current function
+    handlers: usize,
     register: Register,
     label: Register,
     last_label: Register,
     next_const: i32,
+    lambdas: Vec<Lambda>,
+    next_lambda: i32,
+    // Top level functions used as values, which need a closure record
+    function_values: Vec<String>,
+    generics: Generics,
+    // Instances of generic functions waiting to be generated, and names of all of them
+    instances: Vec<Instance>,
+    instance_names: Vec<String>,
 }
 
 impl CGContext {
@@ -90,15 +143,32 @@ impl CGContext {
         CGContext {
             vars: Vars::new(),
             types: Types::new(),
+            globals: Types::new(),
+            functions: Types::new(),
             register: Register::Var(1),
             label: Register::Label(1),
             last_label: Register::Label(1),
             output: vec![],
             consts: Consts::new(),
+            classes: Classes::new(),
+            variants: Variants::new(),
+            class: None,
+            loops: vec![],
+            handlers: 0,
             next_const: 0,
+            lambdas: vec![],
+            next_lambda: 0,
+            function_values: vec![],
+            generics: Generics::new(),
+            instances: vec![],
+            instance_names: vec![],
         }
     }
 
+    pub fn has_var(&self, id: &String) -> bool {
+        self.vars.contains_key(id)
+    }
+
     pub fn get_register(&self, id: &String) -> Register {
         *(self.vars.get(id).unwrap())
     }
@@ -119,7 +189,148 @@ impl CGContext {
     }
 
     pub fn add_function(&mut self, id: &String, t: &Type) {
-        self.types.insert(id.clone(), t.clone());
+        self.functions.insert(id.clone(), t.clone());
+    }
+
+    pub fn has_function(&self, id: &String) -> bool {
+        self.functions.contains_key(id)
+    }
+
+    pub fn get_function_type(&self, id: &String) -> Type {
+        self.functions.get(id).unwrap().clone()
+    }
+
+    pub fn add_lambda(&mut self, captures: Vec<(String, Type)>, args: &Vec<Arg>, ret_type: Type, body: &Expr) -> String {
+        let name = format!(".lambda.{}", self.next_lambda);
+        self.next_lambda = self.next_lambda + 1;
+        self.lambdas.push(Lambda {
+            name: name.clone(),
+            class: self.class.clone(),
+            captures: captures,
+            args: args.clone(),
+            ret_type: ret_type,
+            body: body.clone(),
+        });
+        name
+    }
+
+    pub fn take_lambdas(&mut self) -> Vec<Lambda> {
+        self.lambdas.drain(..).collect()
+    }
+
+    pub fn add_function_value(&mut self, id: &String) {
+        if !self.function_values.contains(id) {
+            self.function_values.push(id.clone());
+        }
+    }
+
+    pub fn function_values(&self) -> Vec<String> {
+        self.function_values.clone()
+    }
+
+    // Copy of the scope without the generated code, used to look up types of lambda bodies
+    pub fn scope(&self) -> CGContext {
+        CGContext {
+            output: vec![],
+            vars: self.vars.clone(),
+            types: self.types.clone(),
+            globals: self.globals.clone(),
+            functions: self.functions.clone(),
+            consts: Consts::new(),
+            classes: self.classes.clone(),
+            variants: self.variants.clone(),
+            class: self.class.clone(),
+            loops: vec![],
+            handlers: 0,
+            register: self.register,
+            label: self.label,
+            last_label: self.last_label,
+            next_const: self.next_const,
+            lambdas: vec![],
+            next_lambda: self.next_lambda,
+            function_values: vec![],
+            generics: self.generics.clone(),
+            instances: vec![],
+            instance_names: vec![],
+        }
+    }
+
+    pub fn add_generic(&mut self, id: &String, type_params: &Vec<String>, fun: &Def) {
+        self.generics.insert(id.clone(), (type_params.clone(), fun.clone()));
+    }
+
+    pub fn has_generic(&self, id: &String) -> bool {
+        self.generics.contains_key(id)
+    }
+
+    pub fn get_generic(&self, id: &String) -> &(Vec<String>, Def) {
+        self.generics.get(id).unwrap()
+    }
+
+    pub fn add_instance(&mut self, name: &String, generic: &String, type_args: Vec<Type>) {
+        if !self.instance_names.contains(name) {
+            self.instance_names.push(name.clone());
+            self.instances.push((name.clone(), generic.clone(), type_args));
+        }
+    }
+
+    pub fn take_instances(&mut self) -> Vec<Instance> {
+        self.instances.drain(..).collect()
+    }
+
+    pub fn add_global(&mut self, id: &String, t: &Type) {
+        self.globals.insert(id.clone(), t.clone());
+    }
+
+    pub fn get_global_type(&self, id: &String) -> Type {
+        self.globals.get(id).unwrap().clone()
+    }
+
+    pub fn add_class(&mut self, name: &String, class: Class) {
+        self.classes.insert(name.clone(), class);
+    }
+
+    pub fn has_class(&self, name: &String) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    pub fn get_class(&self, name: &String) -> Class {
+        self.classes.get(name).unwrap().clone()
+    }
+
+    pub fn get_field(&self, class: &String, field: &String) -> (usize, Type) {
+        let fields = &self.classes.get(class).unwrap().fields;
+        let index = fields.iter().position(|f| &f.1 == field).unwrap();
+        (index, fields[index].0.clone())
+    }
+
+    pub fn has_field(&self, class: &String, field: &String) -> bool {
+        self.classes.get(class).unwrap().fields.iter().any(|f| &f.1 == field)
+    }
+
+    pub fn get_method(&self, class: &String, method: &String) -> Option<(usize, Type)> {
+        let methods = &self.classes.get(class).unwrap().methods;
+        methods.iter().position(|m| &m.0 == method).map(|index| (index, methods[index].2.clone()))
+    }
+
+    pub fn add_variant(&mut self, id: &String, enum_name: &String, tag: i32, fields: Vec<Type>) {
+        self.variants.insert(id.clone(), (enum_name.clone(), tag, fields));
+    }
+
+    pub fn has_variant(&self, id: &String) -> bool {
+        self.variants.contains_key(id)
+    }
+
+    pub fn get_variant(&self, id: &String) -> (String, i32, Vec<Type>) {
+        self.variants.get(id).unwrap().clone()
+    }
+
+    pub fn current_class(&self) -> Option<String> {
+        self.class.clone()
+    }
+
+    pub fn set_current_class(&mut self, class: Option<String>) {
+        self.class = class;
     }
 
     pub fn next_register(&mut self) -> Register {
@@ -147,9 +358,9 @@ impl CGContext {
         self.add_code(format!("L{}:", label.unwrap()));
     }
 
-    pub fn write(self, file: &mut File) -> io::Result<()> {
+    pub fn write<W: Write>(self, out: &mut W) -> io::Result<()> {
         for line in self.output {
-            file.write_fmt(format_args!("{}\n", line))?
+            out.write_fmt(format_args!("{}\n", line))?
         }
         Ok(())
     }
@@ -166,7 +377,7 @@ impl CGContext {
         self.next_const = self.next_const + 1;
 
         self.consts.insert(s.clone(), const_no);
-        let hex = s.bytes().fold(String::new(), |acc, char| format!("{}\\{:X}", acc, char));
+        let hex = s.bytes().fold(String::new(), |acc, char| format!("{}\\{:02X}", acc, char));
         let code = format!("{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"", const_no, s.len() + 1, hex);
         self.output.insert(0, code);
         const_no
@@ -182,6 +393,39 @@ impl CGContext {
         res
     }
 
+    pub fn in_new_loop<T, F>(&mut self, continue_label: Register, break_label: Register, fun: F) -> T
+        where F: Fn(&mut CGContext) -> T {
+        self.loops.push((continue_label, break_label, self.handlers));
+        let res = self.in_new_scope(fun);
+        self.loops.pop();
+        res
+    }
+
+    pub fn continue_label(&self) -> Register {
+        self.loops.last().unwrap().0
+    }
+
+    pub fn break_label(&self) -> Register {
+        self.loops.last().unwrap().1
+    }
+
+    // Handlers installed since entering the innermost loop, removed by break and continue
+    pub fn loop_handlers(&self) -> usize {
+        self.handlers - self.loops.last().unwrap().2
+    }
+
+    pub fn in_try_block<T, F>(&mut self, fun: F) -> T
+        where F: Fn(&mut CGContext) -> T {
+        self.handlers = self.handlers + 1;
+        let res = self.in_new_scope(fun);
+        self.handlers = self.handlers - 1;
+        res
+    }
+
+    pub fn handlers(&self) -> usize {
+        self.handlers
+    }
+
     pub fn in_new_function_scope<T, F>(&mut self, fun: F) -> T
         where F: Fn(&mut CGContext) -> T {
         let old_vars = self.vars.clone();
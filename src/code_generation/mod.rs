@@ -1,9 +1,10 @@
 mod generation_context;
 mod code_generator;
 
-use std::fs::File;
+use std::io;
+use std::io::Write;
 use ast::*;
 
-pub fn run(out: &mut File, p: &Program) {
-    code_generator::generate(out, p);
+pub fn run<W: Write>(out: &mut W, p: &Program) -> io::Result<()> {
+    code_generator::generate(out, p)
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use ast::Span;
+use utils::{line_and_column, excerpt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    // (Label, Span) of the enclosing context, innermost first
+    pub related: Vec<(String, Span)>,
+}
+
+impl Diagnostic {
+    pub fn report(&self, filename: &str, source: &str) -> String {
+        let prefix = match self.severity {
+            Severity::Error => "err",
+            Severity::Warning => "warning",
+        };
+        let notes = self.related.iter().fold(String::new(), |acc, &(ref label, span)| {
+            let (line, column) = line_and_column(source, span.0);
+            format!("{}note: {}:{}:{}: {}\n", acc, filename, line, column, label)
+        });
+        match self.span {
+            Some(span) => {
+                let (line, column) = line_and_column(source, span.0);
+                format!("{}: {}:{}:{}: {} [{}]\n{}{}", prefix, filename, line, column, self.message, self.code,
+                        excerpt(source, span), notes)
+            },
+            None => format!("{}: {}: {} [{}]\n{}", prefix, filename, self.message, self.code, notes),
+        }
+    }
+
+    pub fn to_json(&self, filename: &str, source: &str) -> String {
+        let related: Vec<String> = self.related.iter()
+            .map(|&(ref label, span)| format!("{{\"label\":{},\"span\":{}}}", json_string(label), json_span(span, source)))
+            .collect();
+        format!("{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"span\":{},\"related\":[{}]}}",
+                json_string(self.severity.name()),
+                json_string(self.code),
+                json_string(&self.message),
+                json_string(filename),
+                self.span.map_or(String::from("null"), |span| json_span(span, source)),
+                related.join(","))
+    }
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+fn json_span(span: Span, source: &str) -> String {
+    let Span(beg, end) = span;
+    let (line, column) = line_and_column(source, beg);
+    let (end_line, end_column) = line_and_column(source, end);
+    format!("{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+            beg, end, line, column, end_line, end_column)
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -0,0 +1,59 @@
+extern crate lalrpop_util;
+
+pub mod ast;
+pub mod diagnostics;
+mod ast_printer;
+mod semantic_analysis;
+mod parser;
+mod code_generation;
+mod optimizer;
+mod utils;
+
+use std::io;
+use std::io::Write;
+use ast::Program;
+use diagnostics::Diagnostic;
+
+pub use parser::parser_errors::SyntaxError;
+pub use semantic_analysis::{Error as SemanticError, ErrStack, TypeInfo};
+pub use semantic_analysis::lints::{Lint, LintConfig};
+
+pub fn parse(source: &str) -> Result<Program, Vec<SyntaxError>> {
+    parser::parse(source)
+}
+
+pub fn check_types(program: &Program) -> Result<TypeInfo, SemanticError> {
+    semantic_analysis::check_types(program)
+}
+
+// Warnings about a program that passed type checking, sorted by position
+pub fn lint(program: &Program, info: &TypeInfo, config: &LintConfig) -> Vec<Diagnostic> {
+    semantic_analysis::lints::lint(program, info, config)
+}
+
+pub fn optimize(program: Program) -> Program {
+    optimizer::optimize(program)
+}
+
+pub fn check_returns(program: &Program) -> Result<(), SemanticError> {
+    semantic_analysis::check_returns(program)
+}
+
+pub fn generate<W: Write>(out: &mut W, program: &Program) -> io::Result<()> {
+    code_generation::run(out, program)
+}
+
+pub enum CompileError {
+    Syntax(Vec<SyntaxError>),
+    Semantic(SemanticError),
+    Io(io::Error),
+}
+
+// The whole pipeline from source to LLVM IR, without lints
+pub fn compile<W: Write>(source: &str, out: &mut W) -> Result<(), CompileError> {
+    let program = parse(source).map_err(CompileError::Syntax)?;
+    check_types(&program).map_err(CompileError::Semantic)?;
+    let program = optimize(program);
+    check_returns(&program).map_err(CompileError::Semantic)?;
+    generate(out, &program).map_err(CompileError::Io)
+}
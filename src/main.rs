@@ -1,15 +1,12 @@
-extern crate lalrpop_util;
+extern crate latte;
 
-pub mod ast;
-mod ast_printer;
-mod semantic_analysis;
-mod parser;
-mod code_generation;
-mod optimizer;
-mod utils;
+mod cli;
 
 use std::fs::File;
 use std::process::Command;
+use cli::ErrorFormat;
+use latte::{SemanticError, LintConfig};
+use latte::diagnostics::{Diagnostic, Severity};
 
 fn use_llvm(ll_path: String, bc_path: String) {
     let ref tmp_bc_path = "TMP.bc";
@@ -31,15 +28,59 @@ fn use_llvm(ll_path: String, bc_path: String) {
     Command::new("rm").arg(tmp_bc_path).status().expect("Unable to remove temporary bc file");
 }
 
+fn report_semantic_error(err: SemanticError, filename: &str, input: &str) -> ! {
+    match cli::get_error_format() {
+        ErrorFormat::Human => cli::print_err(err.report(filename, input)),
+        ErrorFormat::Json => cli::print_diagnostics(&err.diagnostics(), filename, input),
+    }
+}
+
+fn report_warnings(warnings: Vec<Diagnostic>, config: &LintConfig, filename: &str, input: &str) {
+    if warnings.is_empty() {
+        return;
+    }
+    if !config.warnings_as_errors {
+        return cli::print_warnings(&warnings, filename, input);
+    }
+    let errors: Vec<Diagnostic> = warnings.into_iter().map(|warning| Diagnostic { severity: Severity::Error, ..warning }).collect();
+    match cli::get_error_format() {
+        ErrorFormat::Human => {
+            let errors: Vec<String> = errors.iter().map(|err| err.report(filename, input)).collect();
+            cli::print_err(errors.join("\n"))
+        },
+        ErrorFormat::Json => cli::print_diagnostics(&errors, filename, input),
+    }
+}
+
 fn main() {
-    let input = utils::get_input();
-    let filename = utils::get_output_filename(".ll");
+    let lint_config = cli::get_lint_config();
+    let input = cli::get_input();
+    let input_filename = cli::get_input_filename();
+    let program = match latte::parse(&input) {
+        Ok(program) => program,
+        Err(errors) => match cli::get_error_format() {
+            ErrorFormat::Human => {
+                let errors: Vec<String> = errors.iter().map(|err| err.report(&input_filename, &input)).collect();
+                cli::print_err(errors.join("\n"))
+            },
+            ErrorFormat::Json => {
+                let diagnostics: Vec<_> = errors.iter().map(|err| err.diagnostic()).collect();
+                cli::print_diagnostics(&diagnostics, &input_filename, &input)
+            },
+        },
+    };
+    let info = match latte::check_types(&program) {
+        Ok(info) => info,
+        Err(err) => report_semantic_error(err, &input_filename, &input),
+    };
+    report_warnings(latte::lint(&program, &info, &lint_config), &lint_config, &input_filename, &input);
+    let program = latte::optimize(program);
+    if let Err(err) = latte::check_returns(&program) {
+        report_semantic_error(err, &input_filename, &input);
+    }
+    let filename = cli::get_output_filename(".ll");
     let mut output = File::create(filename).unwrap();
-    let program = parser::parse(String::from(input));
-    semantic_analysis::check_types(&program);
-    let program = optimizer::optimize(program);
-    semantic_analysis::check_returns(&program);
-    code_generation::run(&mut output, &program);
-    use_llvm(utils::get_output_filename(".ll"), utils::get_output_filename(".bc"));
+    latte::generate(&mut output, &program).expect("Couldn't write to file");
+    use_llvm(cli::get_output_filename(".ll"), cli::get_output_filename(".bc"));
     println!("OK");
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use ast::*;
 
 pub trait Fold {
@@ -10,14 +11,50 @@ trait IsConstant {
 
 impl Fold for Program {
     fn fold(self) -> Program {
-        Program(self.0.into_iter().map(Def::fold).collect())
+        let mut consts = HashMap::new();
+        let mut defs = vec![];
+        for def in self.0 {
+            defs.push(match def {
+                Def::DConst(t, name, init, span) => {
+                    let value = fold_global(&init, &consts).unwrap_or(init);
+                    consts.insert(name.clone(), value.clone());
+                    Def::DConst(t, name, value, span)
+                },
+                Def::DGlobal(t, name, Some(init), span) => {
+                    let value = fold_global(&init, &consts).unwrap_or(init);
+                    Def::DGlobal(t, name, Some(value), span)
+                },
+                def => def.fold(),
+            });
+        }
+        Program(defs)
+    }
+}
+
+// Initializers of globals fold to literals, references to constants declared
+// before them are replaced with their values
+pub fn fold_global(init: &Expr, consts: &HashMap<String, Expr>) -> Option<Expr> {
+    match init.clone().substitute(consts).fold() {
+        value @ Expr::EIntLit(_, _) |
+        value @ Expr::ELongLit(_, _) |
+        value @ Expr::EDoubleLit(_, _) |
+        value @ Expr::EBoolLit(_, _) |
+        value @ Expr::EStringLit(_, _) |
+        value @ Expr::ENull(_, _) => Some(value),
+        _ => None,
     }
 }
 
 impl Fold for Def {
     fn fold(self) -> Def {
         match self {
-            Def::DFun(t, name, args, stmts) => Def::DFun(t, name, args, stmts.fold()),
+            Def::DFun(t, name, args, stmts, span) => Def::DFun(t, name, args, stmts.fold(), span),
+            Def::DClass(name, parent, fields, methods, span) =>
+                Def::DClass(name, parent, fields, methods.into_iter().map(Def::fold).collect(), span),
+            Def::DGeneric(type_params, fun) => Def::DGeneric(type_params, Box::new(fun.fold())),
+            Def::DGlobal(_, _, _, _) |
+            Def::DConst(_, _, _, _) |
+            Def::DEnum(_, _, _) => self,
         }
     }
 }
@@ -25,32 +62,46 @@ impl Fold for Def {
 impl Fold for Stmt {
     fn fold(self) -> Stmt {
         match self {
-            Stmt::SAss(stmt, expr) => Stmt::SAss(stmt, expr.fold()),
-            Stmt::SBlock(stmts) => Stmt::SBlock(stmts.fold()),
-            Stmt::SDecl(t, items) => Stmt::SDecl(t, items.into_iter().map(Item::fold).collect()),
-            Stmt::SExpr(expr) => Stmt::SExpr(expr.fold()),
-            Stmt::SRet(expr) => Stmt::SRet(expr.fold()),
-            Stmt::SIf(cond, block) => {
+            Stmt::SAss(lhs, expr, span) => Stmt::SAss(lhs.fold(), expr.fold(), span),
+            Stmt::SOpAss(var, op, expr, span) => Stmt::SOpAss(var, op, expr.fold(), span),
+            Stmt::SBlock(stmts, span) => Stmt::SBlock(stmts.fold(), span),
+            Stmt::SDecl(t, items, span) => Stmt::SDecl(t, items.into_iter().map(Item::fold).collect(), span),
+            Stmt::SExpr(expr, span) => Stmt::SExpr(expr.fold(), span),
+            Stmt::SRet(expr, span) => Stmt::SRet(expr.fold(), span),
+            Stmt::SIf(cond, block, span) => {
+                let cond = cond.fold();
+                match cond {
+                    Expr::EBoolLit(true, _) => Stmt::SBlock(vec![*block], span),
+                    Expr::EBoolLit(false, _) => Stmt::Empty(span),
+                    _ => Stmt::SIf(cond, block, span),
+                }
+            },
+            Stmt::SWhile(cond, block, span) => {
                 let cond = cond.fold();
                 match cond {
-                    Expr::EBoolLit(true) => Stmt::SBlock(vec![*block]),
-                    Expr::EBoolLit(false) => Stmt::Empty,
-                    _ => Stmt::SIf(cond, block),
+                    Expr::EBoolLit(false, _) => Stmt::Empty(span),
+                    _ => Stmt::SWhile(cond, block, span),
                 }
             },
-            Stmt::SWhile(cond, block) => {
+            Stmt::SFor(init, cond, step, block, span) => {
+                let init = init.fold();
                 let cond = cond.fold();
                 match cond {
-                    Expr::EBoolLit(false) => Stmt::Empty,
-                    _ => Stmt::SWhile(cond, block),
+                    Expr::EBoolLit(false, _) => Stmt::SBlock(vec![init], span),
+                    _ => Stmt::SFor(Box::new(init), cond, Box::new(step.fold()), block, span),
                 }
             },
-            Stmt::SIfElse(cond, if_block, else_block) => {
+            Stmt::SForEach(t, var, array, block, span) => Stmt::SForEach(t, var, array.fold(), block, span),
+            Stmt::SMatch(expr, cases, span) =>
+                Stmt::SMatch(expr.fold(), cases.into_iter().map(|Case(pattern, stmt)| Case(pattern, stmt.fold())).collect(), span),
+            Stmt::SThrow(expr, span) => Stmt::SThrow(expr.fold(), span),
+            Stmt::STry(block, arg, handler, span) => Stmt::STry(Box::new(block.fold()), arg, Box::new(handler.fold()), span),
+            Stmt::SIfElse(cond, if_block, else_block, span) => {
                 let cond = cond.fold();
                 match cond {
-                    Expr::EBoolLit(true) => Stmt::SBlock(vec![*if_block]),
-                    Expr::EBoolLit(false) => Stmt::SBlock(vec![*else_block]),
-                    _ => Stmt::SIfElse(cond, if_block, else_block),
+                    Expr::EBoolLit(true, _) => Stmt::SBlock(vec![*if_block], span),
+                    Expr::EBoolLit(false, _) => Stmt::SBlock(vec![*else_block], span),
+                    _ => Stmt::SIfElse(cond, if_block, else_block, span),
                 }
             },
             _ => self
@@ -67,7 +118,7 @@ impl Fold for Vec<Stmt> {
 impl Fold for Item {
     fn fold(self) -> Item {
         match self {
-            Item::Init(s, e) => Item::Init(s, e.fold()),
+            Item::Init(s, e, span) => Item::Init(s, e.fold(), span),
             _ => self,
         }
     }
@@ -76,15 +127,25 @@ impl Fold for Item {
 impl IsConstant for Expr {
     fn is_constant(&self) -> bool {
         match *self {
-            Expr::EVar(_) |
-            Expr::EPredef(_) |
-            Expr::EApp(_, _)  => false,
-            Expr::EBoolLit(_) |
-            Expr::EIntLit(_) |
-            Expr::EStringLit(_) => true,
-            Expr::ENeg(ref e) |
-            Expr::ENot(ref e) => e.is_constant(),
-            Expr::EOp(ref lhs, _, ref rhs) => lhs.is_constant() && rhs.is_constant(),
+            Expr::EVar(_, _) |
+            Expr::EPredef(_, _) |
+            Expr::EApp(_, _, _) |
+            Expr::ENewArray(_, _, _) |
+            Expr::EIndex(_, _, _) |
+            Expr::EField(_, _, _) |
+            Expr::EMethodCall(_, _, _, _) |
+            Expr::ENew(_, _) |
+            Expr::ENull(_, _) |
+            Expr::ELambda(_, _, _, _) => false,
+            Expr::EBoolLit(_, _) |
+            Expr::EIntLit(_, _) |
+            Expr::ELongLit(_, _) |
+            Expr::EDoubleLit(_, _) |
+            Expr::EStringLit(_, _) => true,
+            Expr::ENeg(ref e, _) |
+            Expr::ENot(ref e, _) |
+            Expr::EBitNot(ref e, _) => e.is_constant(),
+            Expr::EOp(ref lhs, _, ref rhs, _) => lhs.is_constant() && rhs.is_constant(),
         }
     }
 }
@@ -92,27 +153,76 @@ impl IsConstant for Expr {
 impl Fold for Expr {
     fn fold(self) -> Expr {
         match self {
-            Expr::ENot(expr) => {
+            Expr::ENot(expr, span) => {
                 let expr = expr.fold();
                 match expr {
-                    Expr::EBoolLit(b) => Expr::EBoolLit(!b),
-                    _ => Expr::ENot(Box::new(expr))
+                    Expr::EBoolLit(b, _) => Expr::EBoolLit(!b, span),
+                    _ => Expr::ENot(Box::new(expr), span)
                 }
             },
-            Expr::ENeg(expr) => {
+            Expr::ENeg(expr, span) => {
                 let expr = expr.fold();
                 match expr {
-                    Expr::EIntLit(x) => Expr::EIntLit(-x),
-                    _ => Expr::ENeg(Box::new(expr))
+                    Expr::EIntLit(x, _) => Expr::EIntLit(x.wrapping_neg(), span),
+                    Expr::ELongLit(x, _) => Expr::ELongLit(x.wrapping_neg(), span),
+                    Expr::EDoubleLit(x, _) => Expr::EDoubleLit(-x, span),
+                    _ => Expr::ENeg(Box::new(expr), span)
                 }
             },
-            Expr::EOp(lhs, op, rhs) => {
+            Expr::EBitNot(expr, span) => {
+                let expr = expr.fold();
+                match expr {
+                    Expr::EIntLit(x, _) => Expr::EIntLit(!x, span),
+                    _ => Expr::EBitNot(Box::new(expr), span)
+                }
+            },
+            Expr::EOp(lhs, op, rhs, span) => {
                 let lhs = lhs.fold();
                 let rhs = rhs.fold();
-                op.apply(lhs, rhs)
+                op.apply(lhs, rhs, span)
+            },
+            Expr::EApp(s, args, span) => Expr::EApp(s, args.into_iter().map(Expr::fold).collect(), span),
+            Expr::EPredef(Predef::Convert(t, expr), span) => {
+                let expr = expr.fold();
+                match (t, expr) {
+                    (Type::TInt, Expr::ELongLit(x, _)) => Expr::EIntLit(x as i32, span),
+                    (Type::TLong, Expr::EIntLit(x, _)) => Expr::ELongLit(x as i64, span),
+                    (Type::TDouble, Expr::EIntLit(x, _)) => Expr::EDoubleLit(x as f64, span),
+                    (Type::TDouble, Expr::ELongLit(x, _)) => Expr::EDoubleLit(x as f64, span),
+                    (Type::TInt, Expr::EIntLit(x, _)) => Expr::EIntLit(x, span),
+                    (Type::TLong, Expr::ELongLit(x, _)) => Expr::ELongLit(x, span),
+                    (Type::TDouble, Expr::EDoubleLit(x, _)) => Expr::EDoubleLit(x, span),
+                    // Doubles are not folded into integers, out of range values have no defined result
+                    (t, expr) => Expr::EPredef(Predef::Convert(t, Box::new(expr)), span),
+                }
+            },
+            Expr::EPredef(p, span) => Expr::EPredef(p.fold(), span),
+            Expr::ENewArray(t, size, span) => Expr::ENewArray(t, Box::new(size.fold()), span),
+            Expr::EIndex(array, index, span) =>
+                Expr::EIndex(Box::new(array.fold()), Box::new(index.fold()), span),
+            Expr::EField(expr, field, span) => Expr::EField(Box::new(expr.fold()), field, span),
+            Expr::EMethodCall(object, method, args, span) =>
+                Expr::EMethodCall(Box::new(object.fold()), method, args.into_iter().map(Expr::fold).collect(), span),
+            Expr::ELambda(args, body, captures, span) => Expr::ELambda(args, Box::new(body.fold()), captures, span),
+            _ => self
+        }
+    }
+}
+
+impl Expr {
+    fn substitute(self, consts: &HashMap<String, Expr>) -> Expr {
+        match self {
+            Expr::EVar(id, span) => match consts.get(&id) {
+                Some(value) => value.clone(),
+                None => Expr::EVar(id, span),
             },
-            Expr::EApp(s, args) => Expr::EApp(s, args.into_iter().map(Expr::fold).collect()),
-            Expr::EPredef(p) => Expr::EPredef(p.fold()),
+            Expr::ENeg(expr, span) => Expr::ENeg(Box::new(expr.substitute(consts)), span),
+            Expr::ENot(expr, span) => Expr::ENot(Box::new(expr.substitute(consts)), span),
+            Expr::EBitNot(expr, span) => Expr::EBitNot(Box::new(expr.substitute(consts)), span),
+            Expr::EOp(lhs, op, rhs, span) =>
+                Expr::EOp(Box::new(lhs.substitute(consts)), op, Box::new(rhs.substitute(consts)), span),
+            Expr::EPredef(Predef::Convert(t, expr), span) =>
+                Expr::EPredef(Predef::Convert(t, Box::new(expr.substitute(consts))), span),
             _ => self
         }
     }
@@ -123,74 +233,125 @@ impl Fold for Predef {
         match self {
             Predef::Error |
             Predef::ReadInt |
+            Predef::ReadDouble |
             Predef::ReadString => self,
             Predef::PrintInt(e) => Predef::PrintInt(Box::new(e.fold())),
+            Predef::PrintLong(e) => Predef::PrintLong(Box::new(e.fold())),
+            Predef::PrintDouble(e) => Predef::PrintDouble(Box::new(e.fold())),
             Predef::PrintString(e) => Predef::PrintString(Box::new(e.fold())),
+            Predef::Convert(t, e) => Predef::Convert(t, Box::new(e.fold())),
         }
     }
 }
 
 impl BinOp {
-    fn apply(self, lhs: Expr, rhs: Expr) -> Expr {
-        let op = Expr::EOp(Box::new(lhs.clone()), self, Box::new(rhs.clone()));
+    fn apply(self, lhs: Expr, rhs: Expr, span: Span) -> Expr {
+        let op = Expr::EOp(Box::new(lhs.clone()), self, Box::new(rhs.clone()), span);
         match self {
             BinOp::Add => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EIntLit(x + y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x.wrapping_add(y), span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::ELongLit(x.wrapping_add(y), span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EDoubleLit(x + y, span),
                 _ => op
             },
             BinOp::Sub => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EIntLit(x - y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x.wrapping_sub(y), span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::ELongLit(x.wrapping_sub(y), span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EDoubleLit(x - y, span),
                 _ => op
             },
             BinOp::Mul => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EIntLit(x * y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x.wrapping_mul(y), span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::ELongLit(x.wrapping_mul(y), span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EDoubleLit(x * y, span),
                 _ => op
             },
             BinOp::Div => match (lhs, rhs) {
-                (e, Expr::EIntLit(1)) => e,
-                (Expr::EIntLit(x), Expr::EIntLit(y)) if y != 0 => Expr::EIntLit(x / y),
+                (e, Expr::EIntLit(1, _)) => e,
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) if x.checked_div(y).is_some() => Expr::EIntLit(x / y, span),
+                (e, Expr::ELongLit(1, _)) => e,
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) if x.checked_div(y).is_some() => Expr::ELongLit(x / y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EDoubleLit(x / y, span),
                 _ => op
             },
             BinOp::Mod => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) if y != 0 => Expr::EIntLit(x % y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) if x.checked_rem(y).is_some() => Expr::EIntLit(x % y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) if x.checked_rem(y).is_some() => Expr::ELongLit(x % y, span),
+                _ => op
+            },
+            BinOp::BitAnd => match (lhs, rhs) {
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x & y, span),
+                _ => op
+            },
+            BinOp::BitOr => match (lhs, rhs) {
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x | y, span),
+                _ => op
+            },
+            BinOp::BitXor => match (lhs, rhs) {
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x ^ y, span),
+                _ => op
+            },
+            // Only the lowest five bits of the shift count are used, as in the generated code
+            BinOp::Shl => match (lhs, rhs) {
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x.wrapping_shl(y as u32), span),
+                _ => op
+            },
+            BinOp::Shr => match (lhs, rhs) {
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EIntLit(x.wrapping_shr(y as u32), span),
                 _ => op
             },
             BinOp::And => match (lhs, rhs) {
-                (Expr::EBoolLit(false), _) => Expr::EBoolLit(false),
-                (Expr::EBoolLit(x), Expr::EBoolLit(y)) => Expr::EBoolLit(x && y),
+                (Expr::EBoolLit(false, _), _) => Expr::EBoolLit(false, span),
+                (Expr::EBoolLit(x, _), Expr::EBoolLit(y, _)) => Expr::EBoolLit(x && y, span),
                 _ => op
             },
             BinOp::Or => match (lhs, rhs) {
-                (Expr::EBoolLit(true), _) => Expr::EBoolLit(true),
-                (Expr::EBoolLit(x), Expr::EBoolLit(y)) => Expr::EBoolLit(x || y),
+                (Expr::EBoolLit(true, _), _) => Expr::EBoolLit(true, span),
+                (Expr::EBoolLit(x, _), Expr::EBoolLit(y, _)) => Expr::EBoolLit(x || y, span),
                 _ => op
             },
             BinOp::EQ => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EBoolLit(x == y),
-                (Expr::EStringLit(x), Expr::EStringLit(y)) => Expr::EBoolLit(x == y),
-                (Expr::EBoolLit(x), Expr::EBoolLit(y)) => Expr::EBoolLit(x == y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EBoolLit(x == y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::EBoolLit(x == y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EBoolLit(x == y, span),
+                (Expr::EStringLit(x, _), Expr::EStringLit(y, _)) => Expr::EBoolLit(x == y, span),
+                (Expr::EBoolLit(x, _), Expr::EBoolLit(y, _)) => Expr::EBoolLit(x == y, span),
                 _ => op
             },
             BinOp::NEQ => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EBoolLit(x != y),
-                (Expr::EStringLit(x), Expr::EStringLit(y)) => Expr::EBoolLit(x != y),
-                (Expr::EBoolLit(x), Expr::EBoolLit(y)) => Expr::EBoolLit(x != y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EBoolLit(x != y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::EBoolLit(x != y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EBoolLit(x != y, span),
+                (Expr::EStringLit(x, _), Expr::EStringLit(y, _)) => Expr::EBoolLit(x != y, span),
+                (Expr::EBoolLit(x, _), Expr::EBoolLit(y, _)) => Expr::EBoolLit(x != y, span),
                 _ => op
             },
             BinOp::LT => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EBoolLit(x < y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EBoolLit(x < y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::EBoolLit(x < y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EBoolLit(x < y, span),
+                (Expr::EStringLit(x, _), Expr::EStringLit(y, _)) => Expr::EBoolLit(x < y, span),
                 _ => op
             },
             BinOp::LE => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EBoolLit(x <= y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EBoolLit(x <= y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::EBoolLit(x <= y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EBoolLit(x <= y, span),
+                (Expr::EStringLit(x, _), Expr::EStringLit(y, _)) => Expr::EBoolLit(x <= y, span),
                 _ => op
             },
             BinOp::GT => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EBoolLit(x > y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EBoolLit(x > y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::EBoolLit(x > y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EBoolLit(x > y, span),
+                (Expr::EStringLit(x, _), Expr::EStringLit(y, _)) => Expr::EBoolLit(x > y, span),
                 _ => op
             },
             BinOp::GE => match (lhs, rhs) {
-                (Expr::EIntLit(x), Expr::EIntLit(y)) => Expr::EBoolLit(x >= y),
+                (Expr::EIntLit(x, _), Expr::EIntLit(y, _)) => Expr::EBoolLit(x >= y, span),
+                (Expr::ELongLit(x, _), Expr::ELongLit(y, _)) => Expr::EBoolLit(x >= y, span),
+                (Expr::EDoubleLit(x, _), Expr::EDoubleLit(y, _)) => Expr::EBoolLit(x >= y, span),
+                (Expr::EStringLit(x, _), Expr::EStringLit(y, _)) => Expr::EBoolLit(x >= y, span),
                 _ => op
             },
         }
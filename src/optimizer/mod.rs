@@ -1,8 +1,18 @@
-use ast::Program;
+use ast::{Program, Expr};
 use optimizer::constant_folding::Fold;
 
+pub use optimizer::constant_folding::fold_global;
+
 mod constant_folding;
 
 pub fn optimize(program: Program) -> Program {
     program.fold()
-}
\ No newline at end of file
+}
+
+// Value of a condition that constant folding reduces to a literal
+pub fn constant_condition(cond: &Expr) -> Option<bool> {
+    match cond.clone().fold() {
+        Expr::EBoolLit(value, _) => Some(value),
+        _ => None,
+    }
+}
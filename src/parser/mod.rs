@@ -3,59 +3,93 @@ pub mod parser;
 pub mod parser_errors;
 
 use ast::Program;
-use utils::print_err;
+use parser::parser_errors::SyntaxError;
 
-pub fn parse(s: String) -> Program {
-    match parser::parse_Program(remove_comments(&s).as_str()) {
-        Ok(program) => program,
-        Err(err) => {
-            print_err(parser_errors::print_error(err, s));
-            unreachable!()
-        },
+// Syntax errors recovered from at statement and function boundaries are
+// returned together with the one that stopped the parser, if any
+pub fn parse(s: &str) -> Result<Program, Vec<SyntaxError>> {
+    let input = remove_comments(s);
+    let mut recovered = vec![];
+    let result = parser::parse_Program(&mut recovered, input.as_str());
+    let mut errors: Vec<SyntaxError> = recovered.into_iter()
+        .map(|recovery| parser_errors::to_syntax_error(recovery.error, s))
+        .collect();
+    match result {
+        Ok(ref program) if errors.is_empty() => return Ok(program.clone()),
+        Ok(_) => (),
+        Err(err) => errors.push(parser_errors::to_syntax_error(err, s)),
     }
+    Err(errors)
 }
 
-fn remove_comments(s: &String) -> String {
-    let mut last = ' ';
+pub fn unescape(literal: &str) -> Result<String, (String, usize)> {
+    let mut res = String::new();
+    let mut chars = literal[1..literal.len() - 1].char_indices();
+    while let Some((pos, c)) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some((_, 'n')) => res.push('\n'),
+            Some((_, 't')) => res.push('\t'),
+            Some((_, '"')) => res.push('"'),
+            Some((_, '\\')) => res.push('\\'),
+            Some((_, c)) => return Err((format!("\\{}", c), pos + 1)),
+            None => return Err((String::from("\\"), pos + 1)),
+        }
+    }
+    Ok(res)
+}
+
+// Comments are blanked out rather than removed, so that byte offsets
+// reported by the parser still point into the original source
+fn remove_comments(s: &str) -> String {
     let mut in_line_comment = false;
     let mut in_multi_comment = false;
     let mut in_quote = false;
+    let mut escaped = false;
     let mut res = String::new();
-    for c in s.chars() {
-        if !in_line_comment && !in_multi_comment && !in_quote {
-            if last == '/' && c == '/' {
-                in_line_comment = true;
-            } else if last == '/' && c == '*' {
-                in_multi_comment = true;
-            } else if last == '/' {
-                res = format!("{}{}{}", res, last, c);
-            } else if c == '/' {
-
-            } else if c == '\"' {
-                in_quote = true;
-                res = format!("{}{}", res, c);
-            } else {
-                res = format!("{}{}", res, c);
-            }
-        } else if in_line_comment {
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = chars.peek().cloned();
+        if in_line_comment {
             if c == '\n' {
-                res = format!("{}\n", res);
                 in_line_comment = false;
             }
         } else if in_multi_comment {
-            if last == '*' && c == '/' {
-                res = format!("{}\n", res);
+            if c == '*' && next == Some('/') {
+                chars.next();
+                res.push_str("  ");
                 in_multi_comment = false;
-                last = ' ';
                 continue;
             }
+        } else if in_quote {
+            if c == '\"' && !escaped {
+                in_quote = false;
+            }
+            escaped = c == '\\' && !escaped;
+            res.push(c);
+            continue;
+        } else if c == '/' && next == Some('/') {
+            in_line_comment = true;
+        } else if c == '/' && next == Some('*') {
+            chars.next();
+            res.push_str("  ");
+            in_multi_comment = true;
+            continue;
         } else {
             if c == '\"' {
-                in_quote = false;
+                in_quote = true;
             }
-            res = format!("{}{}", res, c);
+            res.push(c);
+            continue;
+        }
+        if c == '\n' {
+            res.push(c);
+        } else {
+            res.push_str(&" ".repeat(c.len_utf8()));
         }
-        last = c;
     }
     res
-}
\ No newline at end of file
+}
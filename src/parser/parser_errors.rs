@@ -1,56 +1,113 @@
 use lalrpop_util::ParseError;
+use ast::Span;
+use diagnostics::{Diagnostic, Severity};
+use utils::{line_and_column, excerpt};
 
 #[derive(Debug)]
 pub enum ErrorType {
     OverflowError,
+    InvalidCast,
+    InvalidEscape,
+    InvalidType,
 }
 
 type Error<'a> = ParseError<usize, (usize, &'a str), (ErrorType, String, usize)>;
 
-pub fn print_error<'a>(err: Error<'a>, input: String) -> String {
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub code: &'static str,
+    pub message: String,
+    // Empty for errors pointing at a single position, None at the end of input
+    pub span: Option<Span>,
+}
+
+impl SyntaxError {
+    // Same format as the reports of semantic errors
+    pub fn report(&self, filename: &str, input: &str) -> String {
+        let span = self.span.unwrap_or(Span(input.len(), input.len()));
+        let (line, column) = line_and_column(input, span.0);
+        format!("err: {}:{}:{}: {}\n{}", filename, line, column, self.message, excerpt(input, span))
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: self.code,
+            message: self.message.clone(),
+            span: self.span,
+            related: vec![],
+        }
+    }
+}
+
+pub fn to_syntax_error<'a>(err: Error<'a>, input: &str) -> SyntaxError {
     match err {
         ParseError::InvalidToken { location } => invalid(input, location),
-        ParseError::UnrecognizedToken { token: None, .. } => eof(),
-        ParseError::UnrecognizedToken { token: Some((beg, t, end)), .. } => unrecognized(input, t.1, beg, end),
-        ParseError::User { error: (err_type, err, loc) } => user(input, err_type, err, loc),
-        x => format!("{:?}", x),
+        ParseError::UnrecognizedToken { token: None, expected } => eof(expected),
+        ParseError::UnrecognizedToken { token: Some((beg, t, end)), expected } =>
+            unrecognized(t.1, beg, end, expected),
+        ParseError::User { error: (err_type, err, loc) } => user(err_type, err, loc),
+        x => SyntaxError { code: "E0000", message: format!("{:?}", x), span: None },
     }
 }
 
-fn eof() -> String {
-    format!("err: unexpected EOF")
+fn eof(expected: Vec<String>) -> SyntaxError {
+    SyntaxError {
+        code: "E0002",
+        message: format!("unexpected EOF{}", expected_tokens(expected)),
+        span: None,
+    }
 }
 
-fn invalid(s: String, loc: usize) -> String  {
-    let (line_no, token_no) = count_line_and_pos(&s, loc);
-    format!("err: {}:{}: invalid token '{}'", line_no, token_no, s.chars().nth(loc).unwrap())
+fn invalid(s: &str, loc: usize) -> SyntaxError {
+    SyntaxError {
+        code: "E0001",
+        message: format!("invalid token '{}'", s[loc..].chars().next().unwrap()),
+        span: Some(Span(loc, loc)),
+    }
 }
 
-fn unrecognized(s: String, token: &str, beg: usize, end: usize) -> String {
-    let (line_no, token_no) = count_line_and_pos(&s, beg);
-    format!("err: {}:{}-{}: unexpected token '{}'", line_no, token_no, token_no + (end - beg), token)
+fn unrecognized(token: &str, beg: usize, end: usize, expected: Vec<String>) -> SyntaxError {
+    SyntaxError {
+        code: "E0002",
+        message: format!("unexpected token '{}'{}", token, expected_tokens(expected)),
+        span: Some(Span(beg, end)),
+    }
 }
 
-fn user(s: String, err_type: ErrorType, err: String, loc: usize) -> String {
-    let (line_no, token_no) = count_line_and_pos(&s, loc);
-    match err_type {
-        ErrorType::OverflowError => format!("err: {}:{}: integer number too large: {} ", line_no, token_no, err),
+fn expected_tokens(expected: Vec<String>) -> String {
+    if expected.is_empty() {
+        return String::new();
     }
+    let expected: Vec<String> = expected.into_iter().map(|token| describe_token(&token)).collect();
+    format!(", expected one of: {}", expected.join(", "))
 }
 
-fn count_line_and_pos(s: &String, loc: usize) -> (usize, usize) {
-    let mut line_no = 1;
-    let mut token_no = 1;
-    for (i, c) in s.chars().enumerate() {
-        if c == '\n' {
-            line_no += 1;
-            token_no = 0;
-        }
-        if loc == i {
-            break;
-        }
-        token_no += 1;
-    };
+// Regex terminals are named after what they match, literal ones are left quoted
+fn describe_token(token: &str) -> String {
+    if token.starts_with("r#") {
+        String::from(match token {
+            t if t.contains("[eE]") => "double literal",
+            t if t.contains("[0-9]+L") => "long literal",
+            t if t.contains("[0-9]") && !t.contains("[a-zA-Z_]") => "integer",
+            t if t.contains("[a-zA-Z_]") => "identifier",
+            _ => "string literal",
+        })
+    } else {
+        token.to_string()
+    }
+}
 
-    (line_no, token_no)
-}
\ No newline at end of file
+fn user(err_type: ErrorType, err: String, loc: usize) -> SyntaxError {
+    let (code, message) = match err_type {
+        ErrorType::OverflowError => ("E0003", format!("integer number too large: {} ", err)),
+        ErrorType::InvalidCast => ("E0004", format!("null can only be cast to a class type, found {}", err)),
+        ErrorType::InvalidEscape => ("E0005", format!("invalid escape sequence '{}' in string literal", err)),
+        ErrorType::InvalidType => ("E0006", format!("expected a parameter type, found {}", err)),
+    };
+    SyntaxError {
+        code: code,
+        message: message,
+        span: Some(Span(loc, loc)),
+    }
+}
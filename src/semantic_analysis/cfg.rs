@@ -0,0 +1,188 @@
+use ast::*;
+use optimizer::constant_condition;
+
+type Block = usize;
+
+const ENTRY: Block = 0;
+// Reached by falling off the end of the function body
+const EXIT: Block = 1;
+// Reached by return statements
+const RETURN: Block = 2;
+
+// Control flow graph of a function body. Blocks only record their successors,
+// edges out of branches whose condition is constant are left out.
+pub struct Cfg {
+    successors: Vec<Vec<Block>>,
+    // (Continue target, Break target) of enclosing loops
+    loops: Vec<(Block, Block)>,
+}
+
+impl Cfg {
+    pub fn new(body: &Vec<Stmt>) -> Cfg {
+        let mut cfg = Cfg::empty();
+        let end = cfg.add_stmts(ENTRY, body);
+        cfg.add_edge(end, EXIT);
+        cfg
+    }
+
+    // Whether control can reach the end of the statement, jumps out of it don't
+    pub fn completes(stmt: &Stmt) -> bool {
+        let mut cfg = Cfg::empty();
+        let end = cfg.add_stmt(ENTRY, stmt);
+        cfg.add_edge(end, EXIT);
+        !cfg.all_paths_return()
+    }
+
+    pub fn all_paths_return(&self) -> bool {
+        !self.reachable().contains(&EXIT)
+    }
+
+    fn empty() -> Cfg {
+        Cfg {
+            successors: vec![vec![], vec![], vec![]],
+            loops: vec![],
+        }
+    }
+
+    fn reachable(&self) -> Vec<Block> {
+        let mut visited = vec![ENTRY];
+        let mut stack = vec![ENTRY];
+        while let Some(block) = stack.pop() {
+            for &next in &self.successors[block] {
+                if !visited.contains(&next) {
+                    visited.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        visited
+    }
+
+    fn new_block(&mut self) -> Block {
+        self.successors.push(vec![]);
+        self.successors.len() - 1
+    }
+
+    fn add_edge(&mut self, from: Block, to: Block) {
+        self.successors[from].push(to);
+    }
+
+    // Control is never transferred to the block following a jump
+    fn jump(&mut self, from: Block, to: Block) -> Block {
+        self.add_edge(from, to);
+        self.new_block()
+    }
+
+    fn add_stmts(&mut self, block: Block, stmts: &Vec<Stmt>) -> Block {
+        stmts.iter().fold(block, |block, stmt| self.add_stmt(block, stmt))
+    }
+
+    // Returns the block in which control continues after the statement
+    fn add_stmt(&mut self, block: Block, stmt: &Stmt) -> Block {
+        match *stmt {
+            Stmt::SRet(_, _) |
+            Stmt::SVRet(_) => self.jump(block, RETURN),
+            Stmt::SThrow(_, _) |
+            Stmt::SExpr(Expr::EPredef(Predef::Error, _), _) => self.new_block(),
+            Stmt::SBreak(_) => match self.loops.last().cloned() {
+                Some((_, break_target)) => self.jump(block, break_target),
+                None => self.new_block(),
+            },
+            Stmt::SContinue(_) => match self.loops.last().cloned() {
+                Some((continue_target, _)) => self.jump(block, continue_target),
+                None => self.new_block(),
+            },
+            Stmt::SBlock(ref stmts, _) => self.add_stmts(block, stmts),
+            Stmt::SIf(ref cond, ref stmt, _) => {
+                let after = self.new_block();
+                self.add_branch(block, cond, stmt, after, true);
+                if constant_condition(cond) != Some(true) {
+                    self.add_edge(block, after);
+                }
+                after
+            },
+            Stmt::SIfElse(ref cond, ref if_stmt, ref else_stmt, _) => {
+                let after = self.new_block();
+                self.add_branch(block, cond, if_stmt, after, true);
+                self.add_branch(block, cond, else_stmt, after, false);
+                after
+            },
+            // Matches are exhaustive, so one of the arms is always taken
+            Stmt::SMatch(_, ref cases, _) => {
+                let after = self.new_block();
+                for case in cases {
+                    let arm = self.new_block();
+                    self.add_edge(block, arm);
+                    let end = self.add_stmt(arm, &case.1);
+                    self.add_edge(end, after);
+                }
+                after
+            },
+            // The handler may be entered before any statement of the guarded block completes
+            Stmt::STry(ref body, _, ref handler, _) => {
+                let after = self.new_block();
+                for stmt in vec![body, handler] {
+                    let start = self.new_block();
+                    self.add_edge(block, start);
+                    let end = self.add_stmt(start, stmt);
+                    self.add_edge(end, after);
+                }
+                after
+            },
+            Stmt::SWhile(ref cond, ref body, _) => {
+                let cond_block = self.new_block();
+                self.add_edge(block, cond_block);
+                self.add_loop(cond_block, cond_block, Some(cond), body)
+            },
+            Stmt::SFor(ref init, ref cond, ref step, ref body, _) => {
+                let block = self.add_stmt(block, init);
+                let cond_block = self.new_block();
+                let step_block = self.new_block();
+                self.add_edge(block, cond_block);
+                let step_end = self.add_stmt(step_block, step);
+                self.add_edge(step_end, cond_block);
+                self.add_loop(cond_block, step_block, Some(cond), body)
+            },
+            Stmt::SForEach(_, _, _, ref body, _) => {
+                let cond_block = self.new_block();
+                self.add_edge(block, cond_block);
+                self.add_loop(cond_block, cond_block, None, body)
+            },
+            Stmt::Empty(_) |
+            Stmt::SDecl(_, _, _) |
+            Stmt::SAss(_, _, _) |
+            Stmt::SInc(_, _) |
+            Stmt::SDecr(_, _) |
+            Stmt::SOpAss(_, _, _, _) |
+            Stmt::SExpr(_, _) => block,
+        }
+    }
+
+    // Adds the branch taken when the condition evaluates to `value`
+    fn add_branch(&mut self, block: Block, cond: &Expr, stmt: &Stmt, after: Block, value: bool) {
+        let branch = self.new_block();
+        if constant_condition(cond).map_or(true, |constant| constant == value) {
+            self.add_edge(block, branch);
+        }
+        let end = self.add_stmt(branch, stmt);
+        self.add_edge(end, after);
+    }
+
+    // Loops without a condition (foreach) can both enter the body and exit
+    fn add_loop(&mut self, cond_block: Block, continue_target: Block, cond: Option<&Expr>, body: &Stmt) -> Block {
+        let body_block = self.new_block();
+        let after = self.new_block();
+        let constant = cond.and_then(constant_condition);
+        if constant != Some(false) {
+            self.add_edge(cond_block, body_block);
+        }
+        if constant != Some(true) {
+            self.add_edge(cond_block, after);
+        }
+        self.loops.push((continue_target, after));
+        let end = self.add_stmt(body_block, body);
+        self.loops.pop();
+        self.add_edge(end, continue_target);
+        after
+    }
+}
@@ -1,86 +1,252 @@
 use std::fmt;
-use ast::Type;
+use std::usize;
+use ast::{Type, Expr, Span};
+use diagnostics::{Diagnostic, Severity};
+use utils::{line_and_column, excerpt};
 
 pub type TError<T> = Result<T, ErrStack>;
-pub type RError = Result<(), String>;
+pub type RError = TError<()>;
 
 pub struct ErrStack {
+    code: &'static str,
     err: String,
-    stack: Vec<String>,
+    span: Option<Span>,
+    // Enclosing nodes, innermost first
+    stack: Vec<(String, Option<Span>)>,
 }
 
 impl ErrStack {
-    pub fn new(err: String) -> ErrStack {
+    pub fn new(code: &'static str, err: String) -> ErrStack {
         ErrStack {
+            code: code,
             err: err,
+            span: None,
             stack: vec![],
         }
     }
 
     pub fn undeclared(id: &String) -> ErrStack {
-        Self::new(format!("use of undeclared identifier {}", id))
+        Self::new("E0101", format!("use of undeclared identifier {}", id))
     }
 
     pub fn redefinition(id: &String) -> ErrStack {
-        Self::new(format!("redefinition of identifier {}", id))
+        Self::new("E0102", format!("redefinition of identifier {}", id))
     }
 
     pub fn op_not_defined(lhs: Type, rhs: Type) -> ErrStack {
-        Self::new(format!("operation not defined for {} and {}", lhs, rhs))
+        Self::new("E0103", format!("operation not defined for {} and {}", lhs, rhs))
     }
 
     pub fn incompatible(given: Type, expected: Type) -> ErrStack {
-        Self::new(format!("incompatible types, cannot convert {} to {}", given, expected))
+        Self::new("E0104", format!("incompatible types, cannot convert {} to {}", given, expected))
     }
 
     pub fn not_a_function(id: &String) -> ErrStack {
-        Self::new(format!("{} is not a function", id))
+        Self::new("E0105", format!("{} is not a function", id))
     }
 
     pub fn invalid_call_type(fun: &String, arg_no: usize, given: Type, expected: Type) -> ErrStack {
-        Self::new(format!("invalid argument type in call to function {}, parameter {} cannot be converted from {} to {}", fun, arg_no, given, expected))
+        Self::new("E0106", format!("invalid argument type in call to function {}, parameter {} cannot be converted from {} to {}", fun, arg_no, given, expected))
     }
 
     pub fn invalid_argument_number(fun: &String, args: usize, expected: usize) -> ErrStack {
-        Self::new(format!("invalid parameter count in call to function {}, expected {}, received {}", fun, expected, args))
+        Self::new("E0107", format!("invalid parameter count in call to function {}, expected {}, received {}", fun, expected, args))
     }
 
     pub fn invalid_main_type() -> ErrStack {
-        Self::new(format!("invalid type of the main function"))
+        Self::new("E0108", format!("invalid type of the main function"))
     }
 
     pub fn missing_main() -> ErrStack {
-        Self::new(format!("main function is missing"))
+        Self::new("E0109", format!("main function is missing"))
     }
 
     pub fn void_return_value() -> ErrStack {
-        Self::new(format!("void function cannot return value"))
+        Self::new("E0110", format!("void function cannot return value"))
     }
 
     pub fn void_argument() -> ErrStack {
-        Self::new(format!("arguments cannot be of type void"))
+        Self::new("E0111", format!("arguments cannot be of type void"))
     }
 
     pub fn void_declaration() -> ErrStack {
-        Self::new(format!("cannot declare variable with type void"))
+        Self::new("E0112", format!("cannot declare variable with type void"))
     }
 
-    pub fn add_to_stack<T: fmt::Display>(mut self, within: &T) -> ErrStack {
-        self.stack.push(format!("{}", within));
+    pub fn void_array() -> ErrStack {
+        Self::new("E0113", format!("cannot create array of type void"))
+    }
+
+    pub fn not_an_array(t: Type) -> ErrStack {
+        Self::new("E0114", format!("cannot index value of type {}", t))
+    }
+
+    pub fn no_field(t: Type, field: &String) -> ErrStack {
+        Self::new("E0115", format!("type {} has no field {}", t, field))
+    }
+
+    pub fn not_assignable(expr: &Expr) -> ErrStack {
+        Self::new("E0116", format!("expression {} is not assignable", expr))
+    }
+
+    pub fn class_redefinition(id: &String) -> ErrStack {
+        Self::new("E0117", format!("redefinition of class {}", id))
+    }
+
+    pub fn undeclared_class(id: &String) -> ErrStack {
+        Self::new("E0118", format!("use of undeclared class {}", id))
+    }
+
+    pub fn field_redefinition(class: &String, field: &String) -> ErrStack {
+        Self::new("E0119", format!("redefinition of field {} in class {}", field, class))
+    }
+
+    pub fn no_method(t: Type, method: &String) -> ErrStack {
+        Self::new("E0120", format!("type {} has no method {}", t, method))
+    }
+
+    pub fn cyclic_inheritance(class: &String) -> ErrStack {
+        Self::new("E0121", format!("class {} inherits from itself", class))
+    }
+
+    pub fn invalid_override(class: &String, method: &String) -> ErrStack {
+        Self::new("E0122", format!("method {} in class {} overrides a method with a different type", method, class))
+    }
+
+    pub fn jump_outside_loop(stmt: &str) -> ErrStack {
+        Self::new("E0123", format!("{} statement not within a loop", stmt))
+    }
+
+    pub fn void_field() -> ErrStack {
+        Self::new("E0124", format!("fields cannot be of type void"))
+    }
+
+    pub fn assignment_to_const(id: &String) -> ErrStack {
+        Self::new("E0125", format!("cannot assign to constant {}", id))
+    }
+
+    pub fn not_constant(id: &String) -> ErrStack {
+        Self::new("E0126", format!("initializer of global {} is not a constant expression", id))
+    }
+
+    pub fn method_as_value(id: &String) -> ErrStack {
+        Self::new("E0127", format!("method {} cannot be used as a value", id))
+    }
+
+    pub fn generic_as_value(id: &String) -> ErrStack {
+        Self::new("E0128", format!("generic function {} cannot be used as a value", id))
+    }
+
+    pub fn cannot_infer(fun: &String, type_param: &String) -> ErrStack {
+        Self::new("E0129", format!("cannot infer type argument {} in call to function {}", type_param, fun))
+    }
+
+    pub fn invalid_null(t: &Type) -> ErrStack {
+        Self::new("E0130", format!("null cannot be of type {}", t))
+    }
+
+    pub fn too_many_instantiations(fun: &String) -> ErrStack {
+        Self::new("E0131", format!("too many instantiations of generic function {}", fun))
+    }
+
+    pub fn variant_as_value(id: &String) -> ErrStack {
+        Self::new("E0132", format!("variant {} cannot be used as a value", id))
+    }
+
+    pub fn not_an_enum(t: Type) -> ErrStack {
+        Self::new("E0133", format!("cannot match on value of type {}", t))
+    }
+
+    pub fn no_variant(t: Type, variant: &String) -> ErrStack {
+        Self::new("E0134", format!("type {} has no variant {}", t, variant))
+    }
+
+    pub fn invalid_binding_number(variant: &String, bindings: usize, expected: usize) -> ErrStack {
+        Self::new("E0135", format!("invalid binding count in pattern of variant {}, expected {}, received {}", variant, expected, bindings))
+    }
+
+    pub fn unreachable_arm() -> ErrStack {
+        Self::new("E0136", format!("unreachable match arm, all values it matches are matched before"))
+    }
+
+    pub fn non_exhaustive_match(missing: &Vec<String>) -> ErrStack {
+        Self::new("E0137", format!("non-exhaustive match, variants not covered: {}", missing.join(", ")))
+    }
+
+    pub fn type_redefinition(id: &String) -> ErrStack {
+        Self::new("E0138", format!("redefinition of type {}", id))
+    }
+
+    pub fn invalid_exception(t: Type) -> ErrStack {
+        Self::new("E0139", format!("exceptions must be of type string, found {}", t))
+    }
+
+    pub fn type_param_shadows_type(id: &String) -> ErrStack {
+        Self::new("E0140", format!("type parameter {} has the name of a declared type", id))
+    }
+
+    pub fn missing_return(function: &String) -> ErrStack {
+        Self::new("E0200", format!("not all execution paths yield value in function {}", function))
+    }
+
+    pub fn add_to_stack<T: fmt::Display>(mut self, within: &T, span: Option<Span>) -> ErrStack {
+        self.stack.push((format!("{}", within), span));
         self
     }
-}
 
-pub fn missing_return(function: &String) -> String {
-    format!("not all execution paths yield value in function {}", function)
+    pub fn message(&self) -> &String {
+        &self.err
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn position(&self) -> usize {
+        self.span.map_or(usize::MAX, |span| span.0)
+    }
+
+    pub fn is_duplicate_of(&self, other: &ErrStack) -> bool {
+        self.span == other.span && self.err == other.err
+    }
+
+    // The innermost location wins, outer nodes only fill it in when missing
+    pub fn at(mut self, span: Span) -> ErrStack {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    pub fn report(&self, filename: &str, source: &str) -> String {
+        match self.span {
+            Some(span) => {
+                let (line, column) = line_and_column(source, span.0);
+                format!("err: {}:{}:{}: {}\n{}{}", filename, line, column, self.err, excerpt(source, span), self.stack())
+            },
+            None => format!("{}", self),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: self.code,
+            message: self.err.clone(),
+            span: self.span,
+            related: self.stack.iter()
+                .filter_map(|&(ref item, span)| span.map(|span| (item.lines().next().unwrap_or("").to_string(), span)))
+                .collect(),
+        }
+    }
+
+    fn stack(&self) -> String {
+        self.stack.iter().fold(String::new(), |acc, &(ref item, _)| format!("{}in:\n{}\n", acc, item))
+    }
 }
 
 impl fmt::Display for ErrStack {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(fmt, "err: {}", self.err)?;
-        for item in &self.stack {
-            writeln!(fmt, "in:\n{}", item)?;
-        }
-        Ok(())
+        write!(fmt, "err: {}\n{}", self.err, self.stack())
     }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use ast::*;
+
+// Type parameter -> Type argument
+pub type Bindings = HashMap<String, Type>;
+
+// Type arguments are inferred from the first argument mentioning each type parameter
+pub fn infer_type_args(type_params: &Vec<String>, param_types: &Vec<Type>, arg_types: &Vec<Type>) -> Bindings {
+    let mut bindings = Bindings::new();
+    for (param_type, arg_type) in param_types.iter().zip(arg_types) {
+        infer(type_params, param_type, arg_type, &mut bindings);
+    }
+    bindings
+}
+
+fn infer(type_params: &Vec<String>, param_type: &Type, arg_type: &Type, bindings: &mut Bindings) {
+    match (param_type, arg_type) {
+        (_, &Type::TError) => (),
+        (&Type::TClass(ref name), _) if type_params.contains(name) => {
+            if !bindings.contains_key(name) {
+                bindings.insert(name.clone(), arg_type.clone());
+            }
+        },
+        (&Type::TArray(ref param_elem), &Type::TArray(ref arg_elem)) => infer(type_params, param_elem, arg_elem, bindings),
+        (&Type::TFunc(ref param_ret, ref param_args), &Type::TFunc(ref arg_ret, ref arg_args))
+            if param_args.len() == arg_args.len() => {
+            infer(type_params, param_ret, arg_ret, bindings);
+            for (param_arg, arg_arg) in param_args.iter().zip(arg_args) {
+                infer(type_params, param_arg, arg_arg, bindings);
+            }
+        },
+        _ => (),
+    }
+}
+
+// Name of the specialized function, `$` cannot appear in Latte identifiers
+pub fn mangle(name: &String, type_args: &Vec<Type>) -> String {
+    format!("{}${}", name, type_args.iter().map(mangle_type).collect::<String>())
+}
+
+fn mangle_type(t: &Type) -> String {
+    match *t {
+        Type::TInt => String::from("i"),
+        Type::TLong => String::from("l"),
+        Type::TDouble => String::from("d"),
+        Type::TBool => String::from("b"),
+        Type::TString => String::from("s"),
+        Type::TVoid => String::from("v"),
+        Type::TArray(ref elem_type) => format!("A{}", mangle_type(elem_type)),
+        Type::TClass(ref name) => format!("C{}{}", name.len(), name),
+        Type::TFunc(ref ret_type, ref args) =>
+            format!("F{}{}E", mangle_type(ret_type), args.iter().map(mangle_type).collect::<String>()),
+        Type::TError => unreachable!(),
+    }
+}
+
+pub trait Substitute {
+    fn substitute(&self, bindings: &Bindings) -> Self;
+}
+
+// Specializes a generic function for the given type arguments
+pub fn instantiate(fun: &Def, name: &String, bindings: &Bindings) -> Def {
+    match *fun {
+        Def::DFun(ref ret_type, _, ref args, ref body, span) =>
+            Def::DFun(ret_type.substitute(bindings), name.clone(), args.substitute(bindings), body.substitute(bindings), span),
+        _ => unreachable!(),
+    }
+}
+
+impl Substitute for Type {
+    fn substitute(&self, bindings: &Bindings) -> Type {
+        match *self {
+            Type::TClass(ref name) => bindings.get(name).cloned().unwrap_or(self.clone()),
+            Type::TArray(ref elem_type) => Type::TArray(elem_type.substitute(bindings)),
+            Type::TFunc(ref ret_type, ref args) => Type::TFunc(ret_type.substitute(bindings), args.substitute(bindings)),
+            _ => self.clone(),
+        }
+    }
+}
+
+impl<T: Substitute> Substitute for Vec<T> {
+    fn substitute(&self, bindings: &Bindings) -> Vec<T> {
+        self.iter().map(|item| item.substitute(bindings)).collect()
+    }
+}
+
+impl<T: Substitute> Substitute for Box<T> {
+    fn substitute(&self, bindings: &Bindings) -> Box<T> {
+        Box::new((**self).substitute(bindings))
+    }
+}
+
+impl Substitute for Arg {
+    fn substitute(&self, bindings: &Bindings) -> Arg {
+        Arg(self.0.substitute(bindings), self.1.clone(), self.2)
+    }
+}
+
+impl Substitute for Item {
+    fn substitute(&self, bindings: &Bindings) -> Item {
+        match *self {
+            Item::Init(ref id, ref expr, span) => Item::Init(id.clone(), expr.substitute(bindings), span),
+            Item::NoInit(_, _) => self.clone(),
+        }
+    }
+}
+
+impl Substitute for Case {
+    fn substitute(&self, bindings: &Bindings) -> Case {
+        Case(self.0.clone(), self.1.substitute(bindings))
+    }
+}
+
+impl Substitute for Stmt {
+    fn substitute(&self, bindings: &Bindings) -> Stmt {
+        match *self {
+            Stmt::SDecl(ref t, ref items, span) => Stmt::SDecl(t.substitute(bindings), items.substitute(bindings), span),
+            Stmt::SAss(ref lhs, ref rhs, span) => Stmt::SAss(lhs.substitute(bindings), rhs.substitute(bindings), span),
+            Stmt::SOpAss(ref var, op, ref expr, span) => Stmt::SOpAss(var.clone(), op, expr.substitute(bindings), span),
+            Stmt::SRet(ref expr, span) => Stmt::SRet(expr.substitute(bindings), span),
+            Stmt::SIf(ref cond, ref stmt, span) => Stmt::SIf(cond.substitute(bindings), stmt.substitute(bindings), span),
+            Stmt::SIfElse(ref cond, ref if_stmt, ref else_stmt, span) =>
+                Stmt::SIfElse(cond.substitute(bindings), if_stmt.substitute(bindings), else_stmt.substitute(bindings), span),
+            Stmt::SWhile(ref cond, ref stmt, span) => Stmt::SWhile(cond.substitute(bindings), stmt.substitute(bindings), span),
+            Stmt::SFor(ref init, ref cond, ref step, ref stmt, span) =>
+                Stmt::SFor(init.substitute(bindings), cond.substitute(bindings), step.substitute(bindings),
+                           stmt.substitute(bindings), span),
+            Stmt::SForEach(ref t, ref id, ref array, ref stmt, span) =>
+                Stmt::SForEach(t.substitute(bindings), id.clone(), array.substitute(bindings), stmt.substitute(bindings), span),
+            Stmt::SExpr(ref expr, span) => Stmt::SExpr(expr.substitute(bindings), span),
+            Stmt::SBlock(ref stmts, span) => Stmt::SBlock(stmts.substitute(bindings), span),
+            Stmt::SMatch(ref expr, ref cases, span) => Stmt::SMatch(expr.substitute(bindings), cases.substitute(bindings), span),
+            Stmt::SThrow(ref expr, span) => Stmt::SThrow(expr.substitute(bindings), span),
+            Stmt::STry(ref block, ref arg, ref handler, span) =>
+                Stmt::STry(block.substitute(bindings), arg.substitute(bindings), handler.substitute(bindings), span),
+            Stmt::Empty(_) |
+            Stmt::SInc(_, _) |
+            Stmt::SDecr(_, _) |
+            Stmt::SVRet(_) |
+            Stmt::SBreak(_) |
+            Stmt::SContinue(_) => self.clone(),
+        }
+    }
+}
+
+impl Substitute for Expr {
+    fn substitute(&self, bindings: &Bindings) -> Expr {
+        match *self {
+            Expr::EApp(ref fun, ref args, span) => Expr::EApp(fun.clone(), args.substitute(bindings), span),
+            Expr::ENeg(ref expr, span) => Expr::ENeg(expr.substitute(bindings), span),
+            Expr::ENot(ref expr, span) => Expr::ENot(expr.substitute(bindings), span),
+            Expr::EBitNot(ref expr, span) => Expr::EBitNot(expr.substitute(bindings), span),
+            Expr::EPredef(Predef::PrintInt(ref expr), span) => Expr::EPredef(Predef::PrintInt(expr.substitute(bindings)), span),
+            Expr::EPredef(Predef::PrintLong(ref expr), span) => Expr::EPredef(Predef::PrintLong(expr.substitute(bindings)), span),
+            Expr::EPredef(Predef::PrintDouble(ref expr), span) =>
+                Expr::EPredef(Predef::PrintDouble(expr.substitute(bindings)), span),
+            Expr::EPredef(Predef::PrintString(ref expr), span) =>
+                Expr::EPredef(Predef::PrintString(expr.substitute(bindings)), span),
+            Expr::EPredef(Predef::Convert(ref t, ref expr), span) =>
+                Expr::EPredef(Predef::Convert(t.clone(), expr.substitute(bindings)), span),
+            Expr::EOp(ref lhs, op, ref rhs, span) => Expr::EOp(lhs.substitute(bindings), op, rhs.substitute(bindings), span),
+            Expr::ENewArray(ref t, ref size, span) => Expr::ENewArray(t.substitute(bindings), size.substitute(bindings), span),
+            Expr::EIndex(ref array, ref index, span) => Expr::EIndex(array.substitute(bindings), index.substitute(bindings), span),
+            Expr::EField(ref expr, ref field, span) => Expr::EField(expr.substitute(bindings), field.clone(), span),
+            Expr::EMethodCall(ref object, ref method, ref args, span) =>
+                Expr::EMethodCall(object.substitute(bindings), method.clone(), args.substitute(bindings), span),
+            Expr::ENew(ref class, span) => match bindings.get(class) {
+                Some(&Type::TClass(ref class)) => Expr::ENew(class.clone(), span),
+                _ => self.clone(),
+            },
+            Expr::ENull(ref t, span) => Expr::ENull(t.substitute(bindings), span),
+            Expr::ELambda(ref args, ref body, ref captures, span) =>
+                Expr::ELambda(args.substitute(bindings), body.substitute(bindings), captures.clone(), span),
+            Expr::EVar(_, _) |
+            Expr::EIntLit(_, _) |
+            Expr::ELongLit(_, _) |
+            Expr::EDoubleLit(_, _) |
+            Expr::EBoolLit(_, _) |
+            Expr::EStringLit(_, _) |
+            Expr::EPredef(_, _) => self.clone(),
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use ast::*;
+use diagnostics::{Diagnostic, Severity};
+use optimizer::constant_condition;
+use semantic_analysis::cfg::Cfg;
+use semantic_analysis::type_context::Kind;
+use semantic_analysis::type_info::TypeInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    UnusedFunction,
+    Shadowing,
+    UnusedResult,
+    UnreachableCode,
+}
+
+const ALL_LINTS: [Lint; 6] = [
+    Lint::UnusedVariable,
+    Lint::UnusedParameter,
+    Lint::UnusedFunction,
+    Lint::Shadowing,
+    Lint::UnusedResult,
+    Lint::UnreachableCode,
+];
+
+impl Lint {
+    // Used both in -W/-A flags and as the code of reported diagnostics
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedParameter => "unused-parameter",
+            Lint::UnusedFunction => "unused-function",
+            Lint::Shadowing => "shadowing",
+            Lint::UnusedResult => "unused-result",
+            Lint::UnreachableCode => "unreachable-code",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        ALL_LINTS.iter().cloned().find(|lint| lint.name() == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    enabled: Vec<Lint>,
+    pub warnings_as_errors: bool,
+}
+
+impl LintConfig {
+    // All lints are enabled by default
+    pub fn new() -> LintConfig {
+        LintConfig {
+            enabled: ALL_LINTS.to_vec(),
+            warnings_as_errors: false,
+        }
+    }
+
+    pub fn warn(&mut self, lint: Lint) {
+        if !self.is_enabled(lint) {
+            self.enabled.push(lint);
+        }
+    }
+
+    pub fn allow(&mut self, lint: Lint) {
+        self.enabled.retain(|&enabled| enabled != lint);
+    }
+
+    pub fn warn_all(&mut self) {
+        self.enabled = ALL_LINTS.to_vec();
+    }
+
+    pub fn allow_all(&mut self) {
+        self.enabled.clear();
+    }
+
+    pub fn is_enabled(&self, lint: Lint) -> bool {
+        self.enabled.contains(&lint)
+    }
+}
+
+// Runs on a program that passed type checking. Bindings whose name starts
+// with an underscore are never reported as unused.
+pub fn lint(program: &Program, info: &TypeInfo, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter::new(info, config);
+    for def in &program.0 {
+        match *def {
+            Def::DFun(_, ref name, ref args, ref body, _) =>
+                linter.lint_function(Callee::Function(name.clone()), args, body),
+            Def::DClass(_, _, _, ref methods, _) =>
+                for method in methods {
+                    if let Def::DFun(_, ref name, ref args, ref body, _) = *method {
+                        linter.lint_function(Callee::Method(name.clone()), args, body);
+                    }
+                },
+            Def::DGeneric(_, ref fun) =>
+                if let Def::DFun(_, ref name, ref args, ref body, _) = **fun {
+                    linter.lint_function(Callee::Function(name.clone()), args, body);
+                },
+            Def::DGlobal(_, _, _, _) |
+            Def::DConst(_, _, _, _) |
+            Def::DEnum(_, _, _) => (),
+        }
+    }
+    linter.lint_unused_functions(program);
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.span.map_or(0, |span| span.0));
+    warnings
+}
+
+// Methods are dispatched dynamically, so a call to a method marks every
+// method with that name as used
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Callee {
+    Function(String),
+    Method(String),
+}
+
+struct Binding {
+    id: String,
+    span: Span,
+    is_param: bool,
+    read: bool,
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    info: &'a TypeInfo,
+    scopes: Vec<Vec<Binding>>,
+    calls: HashMap<Callee, Vec<Callee>>,
+    current: Option<Callee>,
+    dead: bool,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(info: &'a TypeInfo, config: &'a LintConfig) -> Linter<'a> {
+        Linter {
+            config: config,
+            info: info,
+            scopes: vec![],
+            calls: HashMap::new(),
+            current: None,
+            dead: false,
+            warnings: vec![],
+        }
+    }
+
+    fn warn(&mut self, lint: Lint, message: String, span: Span, related: Vec<(String, Span)>) {
+        // Code inside a reported unreachable region is not reported again
+        if lint == Lint::UnreachableCode && self.dead {
+            return;
+        }
+        if self.config.is_enabled(lint) {
+            self.warnings.push(Diagnostic {
+                severity: Severity::Warning,
+                code: lint.name(),
+                message: message,
+                span: Some(span),
+                related: related,
+            });
+        }
+    }
+
+    fn lint_function(&mut self, callee: Callee, args: &Vec<Arg>, body: &Vec<Stmt>) {
+        self.current = Some(callee.clone());
+        self.calls.entry(callee).or_insert(vec![]);
+        self.scopes.push(vec![]);
+        for &Arg(_, ref id, span) in args {
+            self.declare(id, span, true);
+        }
+        self.lint_stmts(body);
+        self.pop_scope();
+        self.current = None;
+    }
+
+    fn lint_unused_functions(&mut self, program: &Program) {
+        let mut reachable = HashSet::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(Callee::Function(String::from("main")));
+        while let Some(callee) = queue.pop_front() {
+            if reachable.insert(callee.clone()) {
+                queue.extend(self.calls.get(&callee).cloned().unwrap_or(vec![]));
+            }
+        }
+        for def in &program.0 {
+            let fun = match *def {
+                Def::DGeneric(_, ref fun) => &**fun,
+                ref def => def,
+            };
+            if let Def::DFun(_, ref name, _, _, span) = *fun {
+                if !reachable.contains(&Callee::Function(name.clone())) {
+                    self.warn(Lint::UnusedFunction, format!("function {} is never called from main", name), span, vec![]);
+                }
+            }
+        }
+    }
+
+    fn in_new_scope<T, F>(&mut self, fun: F) -> T where F: FnOnce(&mut Linter<'a>) -> T {
+        self.scopes.push(vec![]);
+        let result = fun(self);
+        self.pop_scope();
+        result
+    }
+
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap_or(vec![]) {
+            if binding.read || binding.id.starts_with("_") {
+                continue;
+            }
+            if binding.is_param {
+                self.warn(Lint::UnusedParameter, format!("unused parameter {}", binding.id), binding.span, vec![]);
+            } else {
+                self.warn(Lint::UnusedVariable, format!("unused variable {}", binding.id), binding.span, vec![]);
+            }
+        }
+    }
+
+    fn declare(&mut self, id: &String, span: Span, is_param: bool) {
+        if let Some(outer) = self.info.shadowed(id, span) {
+            self.warn(Lint::Shadowing, format!("declaration of {} shadows an outer binding", id), span,
+                      vec![(format!("previous declaration of {}", id), outer)]);
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding { id: id.clone(), span: span, is_param: is_param, read: false });
+        }
+    }
+
+    fn add_call(&mut self, callee: Callee) {
+        if let Some(ref current) = self.current {
+            self.calls.entry(current.clone()).or_insert(vec![]).push(callee);
+        }
+    }
+
+    // Statements following one that never completes are reported once, as a whole
+    fn lint_stmts(&mut self, stmts: &Vec<Stmt>) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.lint_stmt(stmt);
+            if Cfg::completes(stmt) || i + 1 == stmts.len() {
+                continue;
+            }
+            let label = String::from("any code following this statement is unreachable");
+            self.warn(Lint::UnreachableCode, String::from("unreachable statement"), stmts[i + 1].span(),
+                      vec![(label, stmt.span())]);
+            let dead = self.dead;
+            self.dead = true;
+            for stmt in &stmts[i + 1..] {
+                self.lint_stmt(stmt);
+            }
+            self.dead = dead;
+            return;
+        }
+    }
+
+    fn lint_dead_branch(&mut self, stmt: &Stmt, cond: &Expr, value: bool) {
+        let label = format!("condition is always {}", value);
+        self.warn(Lint::UnreachableCode, String::from("unreachable branch"), stmt.span(), vec![(label, cond.span())]);
+        let dead = self.dead;
+        self.dead = true;
+        self.in_new_scope(|linter| linter.lint_stmt(stmt));
+        self.dead = dead;
+    }
+
+    fn lint_stmt(&mut self, stmt: &Stmt) {
+        match *stmt {
+            Stmt::SDecl(_, ref items, _) =>
+                for item in items {
+                    if let Item::Init(_, ref expr, _) = *item {
+                        self.lint_expr(expr);
+                    }
+                    self.declare(&item.get_id(), item.span(), false);
+                },
+            Stmt::SAss(ref lhs, ref rhs, _) => {
+                // Assigning to a variable doesn't read it
+                if let Expr::EVar(_, _) = *lhs {} else {
+                    self.lint_expr(lhs);
+                }
+                self.lint_expr(rhs);
+            },
+            Stmt::SOpAss(_, _, ref expr, _) |
+            Stmt::SRet(ref expr, _) |
+            Stmt::SThrow(ref expr, _) => self.lint_expr(expr),
+            Stmt::SIf(ref cond, ref stmt, _) |
+            Stmt::SWhile(ref cond, ref stmt, _) => {
+                self.lint_expr(cond);
+                match constant_condition(cond) {
+                    Some(false) => self.lint_dead_branch(stmt, cond, false),
+                    _ => self.in_new_scope(|linter| linter.lint_stmt(stmt)),
+                }
+            },
+            Stmt::SIfElse(ref cond, ref if_stmt, ref else_stmt, _) => {
+                self.lint_expr(cond);
+                match constant_condition(cond) {
+                    Some(true) => {
+                        self.in_new_scope(|linter| linter.lint_stmt(if_stmt));
+                        self.lint_dead_branch(else_stmt, cond, true);
+                    },
+                    Some(false) => {
+                        self.lint_dead_branch(if_stmt, cond, false);
+                        self.in_new_scope(|linter| linter.lint_stmt(else_stmt));
+                    },
+                    None => {
+                        self.in_new_scope(|linter| linter.lint_stmt(if_stmt));
+                        self.in_new_scope(|linter| linter.lint_stmt(else_stmt));
+                    },
+                }
+            },
+            Stmt::SFor(ref init, ref cond, ref step, ref body, _) =>
+                self.in_new_scope(|linter| {
+                    linter.lint_stmt(init);
+                    linter.lint_expr(cond);
+                    linter.lint_stmt(step);
+                    match constant_condition(cond) {
+                        Some(false) => linter.lint_dead_branch(body, cond, false),
+                        _ => linter.in_new_scope(|linter| linter.lint_stmt(body)),
+                    }
+                }),
+            Stmt::SForEach(_, ref id, ref array, ref body, span) => {
+                self.lint_expr(array);
+                self.in_new_scope(|linter| {
+                    linter.declare(id, span, false);
+                    linter.in_new_scope(|linter| linter.lint_stmt(body));
+                });
+            },
+            Stmt::SExpr(ref expr, _) => {
+                self.lint_expr(expr);
+                match self.info.type_of(expr).cloned() {
+                    Some(Type::TVoid) | None => (),
+                    Some(t) => self.warn(Lint::UnusedResult, format!("result of type {} is discarded", t), expr.span(), vec![]),
+                }
+            },
+            Stmt::SBlock(ref stmts, _) => self.in_new_scope(|linter| linter.lint_stmts(stmts)),
+            Stmt::SMatch(ref expr, ref cases, _) => {
+                self.lint_expr(expr);
+                for &Case(ref pattern, ref stmt) in cases {
+                    self.in_new_scope(|linter| {
+                        if let Pattern::PVariant(_, ref bindings, span) = *pattern {
+                            for id in bindings.iter().filter(|id| *id != "_") {
+                                linter.declare(id, span, false);
+                            }
+                        }
+                        linter.lint_stmt(stmt)
+                    });
+                }
+            },
+            Stmt::STry(ref body, Arg(_, ref id, span), ref handler, _) => {
+                self.in_new_scope(|linter| linter.lint_stmt(body));
+                self.in_new_scope(|linter| {
+                    linter.declare(id, span, false);
+                    linter.lint_stmt(handler)
+                });
+            },
+            Stmt::SBreak(_) |
+            Stmt::SVRet(_) |
+            Stmt::SContinue(_) |
+            Stmt::Empty(_) |
+            Stmt::SInc(_, _) |
+            Stmt::SDecr(_, _) => (),
+        }
+    }
+
+    fn lint_expr(&mut self, expr: &Expr) {
+        match *expr {
+            Expr::EVar(ref id, _) => self.lint_symbol(id, expr),
+            Expr::EApp(ref id, ref args, _) => {
+                self.lint_symbol(id, expr);
+                for arg in args {
+                    self.lint_expr(arg);
+                }
+            },
+            Expr::EMethodCall(ref object, ref method, ref args, _) => {
+                self.add_call(Callee::Method(method.clone()));
+                self.lint_expr(object);
+                for arg in args {
+                    self.lint_expr(arg);
+                }
+            },
+            Expr::ELambda(ref args, ref body, _, _) => self.in_new_scope(|linter| {
+                for &Arg(_, ref id, span) in args {
+                    linter.declare(id, span, true);
+                }
+                linter.lint_expr(body);
+            }),
+            Expr::EPredef(Predef::PrintInt(ref expr), _) |
+            Expr::EPredef(Predef::PrintLong(ref expr), _) |
+            Expr::EPredef(Predef::PrintDouble(ref expr), _) |
+            Expr::EPredef(Predef::PrintString(ref expr), _) |
+            Expr::EPredef(Predef::Convert(_, ref expr), _) |
+            Expr::ENeg(ref expr, _) |
+            Expr::ENot(ref expr, _) |
+            Expr::EBitNot(ref expr, _) |
+            Expr::ENewArray(_, ref expr, _) |
+            Expr::EField(ref expr, _, _) => self.lint_expr(expr),
+            Expr::EOp(ref lhs, _, ref rhs, _) |
+            Expr::EIndex(ref lhs, ref rhs, _) => {
+                self.lint_expr(lhs);
+                self.lint_expr(rhs);
+            },
+            Expr::EIntLit(_, _) |
+            Expr::ELongLit(_, _) |
+            Expr::EDoubleLit(_, _) |
+            Expr::EBoolLit(_, _) |
+            Expr::EStringLit(_, _) |
+            Expr::EPredef(_, _) |
+            Expr::ENew(_, _) |
+            Expr::ENull(_, _) => (),
+        }
+    }
+
+    fn lint_symbol(&mut self, id: &String, expr: &Expr) {
+        match self.info.kind_of(expr) {
+            Some(Kind::Local(_)) => self.mark_read(id),
+            // Referring to a function counts as calling it from the enclosing function
+            Some(Kind::Function) |
+            Some(Kind::Generic) => self.add_call(Callee::Function(id.clone())),
+            Some(Kind::Method) => self.add_call(Callee::Method(id.clone())),
+            Some(Kind::Var) |
+            Some(Kind::Field) |
+            Some(Kind::Variant) |
+            None => (),
+        }
+    }
+
+    fn mark_read(&mut self, id: &String) {
+        let binding = self.scopes.iter_mut().rev()
+            .filter_map(|scope| scope.iter_mut().rev().find(|binding| &binding.id == id))
+            .next();
+        if let Some(binding) = binding {
+            binding.read = true;
+        }
+    }
+}
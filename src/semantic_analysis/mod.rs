@@ -1,36 +1,42 @@
 pub mod type_checker;
+pub mod lints;
+pub mod generics;
+pub mod cfg;
 mod errors;
 mod type_context;
+mod type_info;
 
-use semantic_analysis::errors::*;
-use utils::print_err;
+pub use semantic_analysis::errors::ErrStack;
+pub use semantic_analysis::type_info::TypeInfo;
 use ast::Program;
-use std::fmt;
+use diagnostics::Diagnostic;
 
 pub enum Error {
-    Type(ErrStack),
-    Return(String)
+    Type(Vec<ErrStack>),
+    Return(ErrStack)
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+impl Error {
+    pub fn report(&self, filename: &str, source: &str) -> String {
         match *self {
-            Error::Type(ref stack) => write!(fmt, "{}", stack),
-            Error::Return(ref error) => write!(fmt, "{}", error),
+            Error::Type(ref stacks) =>
+                stacks.iter().map(|stack| stack.report(filename, source)).collect::<Vec<String>>().join("\n"),
+            Error::Return(ref stack) => stack.report(filename, source),
         }
     }
-}
 
-pub fn check_types(program: &Program) {
-    match type_checker::check(program).map_err(|err| Error::Type(err)) {
-        Ok(_) => (),
-        Err(err) => print_err(format!("{}", err)),
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match *self {
+            Error::Type(ref stacks) => stacks.iter().map(ErrStack::diagnostic).collect(),
+            Error::Return(ref stack) => vec![stack.diagnostic()],
+        }
     }
 }
 
-pub fn check_returns(program: &Program) {
-    match type_checker::check_return(program).map_err(|err| Error::Return(err)) {
-        Ok(_) => (),
-        Err(err) => print_err(format!("{}", err)),
-    }
+pub fn check_types(program: &Program) -> Result<TypeInfo, Error> {
+    type_checker::check(program).map_err(|err| Error::Type(err))
+}
+
+pub fn check_returns(program: &Program) -> Result<(), Error> {
+    type_checker::check_return(program).map_err(|err| Error::Return(err))
 }
\ No newline at end of file
@@ -1,78 +1,190 @@
 use std::fmt;
 use std::marker;
 use ast::*;
-use semantic_analysis::errors::{TError, RError, ErrStack, missing_return};
-use semantic_analysis::type_context::{TCContext};
+use semantic_analysis::errors::{TError, RError, ErrStack};
+use semantic_analysis::type_context::{TCContext, Kind};
+use semantic_analysis::type_info::TypeInfo;
+use semantic_analysis::cfg::Cfg;
+use semantic_analysis::generics::{Substitute, infer_type_args, instantiate};
+use optimizer::fold_global;
 
-pub fn check(program: &Program) -> TError<()> {
-    program.do_check(&mut TCContext::new())
+pub fn check(program: &Program) -> Result<TypeInfo, Vec<ErrStack>> {
+    let context = &mut TCContext::new();
+    let result = program.do_check(context);
+    let mut errors = context.take_reported();
+    if let Err(err) = result {
+        errors.push(err);
+    }
+    errors.sort_by_key(ErrStack::position);
+    errors.dedup_by(|err, other| err.is_duplicate_of(other));
+    if errors.is_empty() {
+        Ok(context.take_info())
+    } else {
+        Err(errors)
+    }
 }
 
 trait TypeCheck<T> where Self: fmt::Display + marker::Sized {
     fn check(&self, context: &mut TCContext) -> TError<T> {
-        self.do_check(context).map_err(|err| err.add_to_stack(self))
+        let reported = context.reported_count();
+        let result = self.do_check(context).map_err(|err| match self.location() {
+            Some(span) => err.at(span).add_to_stack(self, Some(span)),
+            None => err.add_to_stack(self, None),
+        });
+        context.add_to_reported_stacks(reported, self, self.location());
+        match (result, self.recovered()) {
+            (Err(err), Some(value)) => {
+                context.report(err);
+                Ok(value)
+            },
+            (Ok(value), _) => {
+                self.checked(&value, context);
+                Ok(value)
+            },
+            (result, _) => result,
+        }
     }
 
     fn do_check(&self, context: &mut TCContext) -> TError<T>;
-}
 
-pub trait Returns {
-    fn check_return(&self) -> bool;
-}
+    fn checked(&self, _value: &T, _context: &mut TCContext) {}
 
-impl Returns for Stmt {
-    fn check_return(&self) -> bool {
-        match *self {
-            Stmt::SVRet |
-            Stmt::SRet(_) => true,
-            Stmt::SIfElse(_, ref b1, ref b2) => b1.check_return() && b2.check_return(),
-            Stmt::SBlock(ref stmts) => stmts.check_return(),
-            _ => false
-        }
+    fn location(&self) -> Option<Span> {
+        None
     }
-}
 
-impl Returns for Vec<Stmt> {
-    fn check_return(&self) -> bool {
-        self.iter().any(Stmt::check_return)
+    // Value to carry on with after reporting an error, None propagates the error instead
+    fn recovered(&self) -> Option<T> {
+        None
     }
 }
 
 pub fn check_return(program: &Program) -> RError {
     for def in &program.0 {
         match *def {
-            Def::DFun(ref t, ref name, _, ref body) =>
-                if t != &Type::TVoid && !body.check_return() {
-                    return Err(missing_return(name));
-                }
+            Def::DFun(ref t, ref name, _, ref body, span) => check_function_return(t, name, body, span)?,
+            Def::DClass(ref class, _, _, ref methods, _) =>
+                for method in methods {
+                    if let Def::DFun(ref t, ref name, _, ref body, span) = *method {
+                        check_function_return(t, &format!("{}.{}", class, name), body, span)?;
+                    }
+                },
+            Def::DGeneric(_, ref fun) =>
+                if let Def::DFun(ref t, ref name, _, ref body, span) = **fun {
+                    check_function_return(t, name, body, span)?;
+                },
+            Def::DGlobal(_, _, _, _) |
+            Def::DConst(_, _, _, _) |
+            Def::DEnum(_, _, _) => (),
         }
     }
     Ok(())
 }
 
+// Missing returns are reported at the closing brace of the function
+fn check_function_return(t: &Type, name: &String, body: &Vec<Stmt>, span: Span) -> RError {
+    if t != &Type::TVoid && !Cfg::new(body).all_paths_return() {
+        Err(ErrStack::missing_return(name).at(Span(span.1 - 1, span.1)))
+    } else {
+        Ok(())
+    }
+}
+
 impl TypeCheck<()> for Program {
     fn do_check(&self, context: &mut TCContext) -> TError<()> {
         let Program(ref defs) = *self;
 
         for def in defs {
             match *def {
-                Def::DFun(_, ref name, _, _) => {
-                    context.add(name, &def.get_type())?;
-                }
+                Def::DFun(_, ref name, _, _, span) => {
+                    if let Err(err) = context.add_function(name, &def.get_type()) {
+                        context.report(err.at(span));
+                    }
+                },
+                Def::DClass(ref name, ref parent, ref fields, ref methods, span) => {
+                    let methods = methods.iter().map(|method| (method.get_name(), method.get_type())).collect();
+                    if let Err(err) = context.add_class(name, parent, fields, methods) {
+                        context.report(err.at(span));
+                    }
+                },
+                Def::DGlobal(ref t, ref name, _, span) => {
+                    if let Err(err) = context.add(name, t, span) {
+                        context.report(err.at(span));
+                    }
+                },
+                Def::DConst(ref t, ref name, _, span) => {
+                    if let Err(err) = context.add_const(name, t, span) {
+                        context.report(err.at(span));
+                    }
+                },
+                Def::DGeneric(ref type_params, ref fun) => {
+                    if let Err(err) = context.add_generic(&def.get_name(), type_params, fun, &def.get_type()) {
+                        context.report(err.at(def.span()));
+                    }
+                },
+                Def::DEnum(ref name, ref variants, span) => {
+                    let fields = variants.iter()
+                        .map(|variant| (variant.0.clone(), variant.1.iter().map(|arg| arg.0.clone()).collect()))
+                        .collect();
+                    if let Err(err) = context.add_enum(name, fields) {
+                        context.report(err.at(span));
+                    }
+                    for variant in variants {
+                        if let Err(err) = context.add_variant(&variant.0, &variant.get_type(name)) {
+                            context.report(err.at(variant.2));
+                        }
+                    }
+                },
+            }
+        }
+
+        // Checking members of a broken hierarchy could loop forever, so this is not recovered from
+        for def in defs {
+            if let Def::DClass(ref name, _, _, _, span) = *def {
+                context.check_hierarchy(name).map_err(|err| err.at(span))?;
             }
         }
 
         for def in defs {
             match *def {
-                Def::DFun(ref ret_type, _, _, _) =>
-                    context.in_new_function(ret_type, |mut ctx| def.check(&mut ctx))?
+                Def::DFun(ref ret_type, _, _, _, _) =>
+                    context.in_new_function(ret_type, |mut ctx| def.check(&mut ctx))?,
+                Def::DClass(_, _, _, _, _) |
+                Def::DGlobal(_, _, _, _) |
+                Def::DConst(_, _, _, _) |
+                Def::DGeneric(_, _) |
+                Def::DEnum(_, _, _) => def.check(context)?,
             };
         }
 
+        check_instantiations(context)?;
         check_main_exists(context)
     }
 }
 
+// Generic functions can instantiate each other without end, e.g. f<T> calling f<T[]>
+const MAX_INSTANTIATIONS: usize = 1000;
+
+// Bodies of generic functions are checked once for each instantiation
+fn check_instantiations(context: &mut TCContext) -> TError<()> {
+    let context = &mut context.without_info();
+    let mut checked = 0;
+    while let Some((name, type_args)) = context.instantiation(checked) {
+        let (type_params, fun) = context.get_generic(&name);
+        if checked == MAX_INSTANTIATIONS {
+            return Err(ErrStack::too_many_instantiations(&name).at(fun.span()));
+        }
+        let bindings = type_params.into_iter().zip(type_args.iter().cloned()).collect();
+        let types = type_args.iter().map(|t| format!("{}", t)).collect::<Vec<String>>();
+        let instance = instantiate(&fun, &format!("{}<{}>", name, types.join(", ")), &bindings);
+        if let Def::DFun(ref ret_type, _, _, _, _) = instance {
+            context.in_new_function(ret_type, |mut ctx| instance.check(&mut ctx))?;
+        }
+        checked += 1;
+    }
+    Ok(())
+}
+
 fn check_main_exists(context: &mut TCContext) -> TError<()> {
     if let Ok(main_type) = context.get(&String::from("main")) {
         if main_type == Type::TFunc(Box::new(Type::TInt), vec![]) {
@@ -86,29 +198,130 @@ fn check_main_exists(context: &mut TCContext) -> TError<()> {
 }
 
 impl TypeCheck<()> for Def {
+    fn location(&self) -> Option<Span> {
+        Some(self.span())
+    }
+
+    fn recovered(&self) -> Option<()> {
+        Some(())
+    }
+
     fn do_check(&self, context: &mut TCContext) -> TError<()> {
         match *self {
-            Def::DFun(_, _, ref args, ref block) => {
+            Def::DFun(ref ret_type, _, ref args, ref block, _) => {
+                check_type(ret_type, context)?;
                 for arg in args {
-                    arg.do_check(context)?;
-                    context.add(&arg.1, &arg.0)?;
+                    if let Err(err) = arg.do_check(context).and(context.add_local(&arg.1, &arg.0, arg.2)) {
+                        context.report(err.at(arg.2));
+                    }
                 }
                 for stmt in block {
                     stmt.check(context)?;
                 }
                 Ok(())
-            }
+            },
+            Def::DClass(ref name, ref parent, ref fields, ref methods, _) => {
+                for (i, &Field(ref t, ref field, span)) in fields.iter().enumerate() {
+                    let inherited = parent.as_ref().map_or(false, |p| context.get_field(p, field).is_ok());
+                    if *t == Type::TVoid {
+                        context.report(ErrStack::void_field().at(span));
+                    } else if let Err(err) = check_type(t, context) {
+                        context.report(err.at(span));
+                    } else if inherited || fields[..i].iter().any(|f| &f.1 == field) {
+                        context.report(ErrStack::field_redefinition(name, field).at(span));
+                    }
+                }
+                for (i, method) in methods.iter().enumerate() {
+                    let method_name = method.get_name();
+                    if methods[..i].iter().any(|m| m.get_name() == method_name) ||
+                        context.get_field(name, &method_name).is_ok() {
+                        context.report(ErrStack::redefinition(&method_name).at(method.span()));
+                    } else if let Some(overridden) = parent.as_ref().and_then(|p| context.get_method(p, &method_name).ok()) {
+                        if overridden != method.get_type() {
+                            context.report(ErrStack::invalid_override(name, &method_name).at(method.span()));
+                        }
+                    }
+                }
+                for method in methods {
+                    if let Def::DFun(ref ret_type, _, _, _, _) = *method {
+                        context.in_new_method(name, ret_type, |mut ctx| method.check(&mut ctx))?;
+                    }
+                }
+                Ok(())
+            },
+            Def::DGlobal(ref t, ref name, ref init, _) => {
+                check_global_type(t, context)?;
+                if let Some(ref init) = *init {
+                    check_global_init(t, name, init, context)?;
+                }
+                Ok(())
+            },
+            Def::DConst(ref t, ref name, ref init, _) => {
+                check_global_type(t, context)?;
+                let value = check_global_init(t, name, init, context)?;
+                context.set_const_value(name, value);
+                Ok(())
+            },
+            // The body is checked once with the type parameters as opaque types, and again for
+            // each instantiation, where operations on values of those types are checked
+            Def::DGeneric(ref type_params, ref fun) => {
+                for (i, type_param) in type_params.iter().enumerate() {
+                    if type_params[..i].contains(type_param) {
+                        return Err(ErrStack::redefinition(type_param));
+                    }
+                    if context.has_class(type_param) || context.has_enum(type_param) {
+                        return Err(ErrStack::type_param_shadows_type(type_param));
+                    }
+                }
+                if let Def::DFun(ref ret_type, _, _, _, _) = **fun {
+                    context.in_new_generic(type_params, ret_type, |mut ctx| fun.do_check(&mut ctx))?;
+                }
+                Ok(())
+            },
+            Def::DEnum(_, ref variants, _) => {
+                for variant in variants {
+                    let fields = &variant.1;
+                    for (i, &Arg(ref t, ref field, span)) in fields.iter().enumerate() {
+                        if *t == Type::TVoid {
+                            context.report(ErrStack::void_field().at(span));
+                        } else if let Err(err) = check_type(t, context) {
+                            context.report(err.at(span));
+                        } else if fields[..i].iter().any(|f| &f.1 == field) {
+                            context.report(ErrStack::redefinition(field).at(span));
+                        }
+                    }
+                }
+                Ok(())
+            },
         }
     }
 }
 
+fn check_global_type(t: &Type, context: &TCContext) -> TError<()> {
+    if *t == Type::TVoid {
+        Err(ErrStack::void_declaration())
+    } else {
+        check_type(t, context)
+    }
+}
+
+// Initializers may only refer to constants declared before them, the folded value is returned
+fn check_global_init(t: &Type, name: &String, init: &Expr, context: &mut TCContext) -> TError<Expr> {
+    expect(init.check(context)?, t.clone(), context)?;
+    fold_global(init, &context.const_values()).ok_or(ErrStack::not_constant(name).at(init.span()))
+}
+
 impl TypeCheck<()> for Arg {
-    fn do_check(&self, _: &mut TCContext) -> TError<()> {
-        let &Arg(ref t, _) = self;
+    fn location(&self) -> Option<Span> {
+        Some(self.2)
+    }
+
+    fn do_check(&self, context: &mut TCContext) -> TError<()> {
+        let &Arg(ref t, _, _) = self;
         if *t == Type::TVoid {
             Err(ErrStack::void_argument())
         } else {
-            Ok(())
+            check_type(t, context)
         }
     }
 }
@@ -116,54 +329,129 @@ impl TypeCheck<()> for Arg {
 impl Def {
     fn get_type(&self) -> Type {
         match *self {
-            Def::DFun(ref ret_type, _, ref args, _) => {
+            Def::DFun(ref ret_type, _, ref args, _, _) => {
                 Type::TFunc(Box::new(ret_type.clone()), args.into_iter().map(|arg| arg.0.clone()).collect())
-            }
+            },
+            Def::DClass(ref name, _, _, _, _) => Type::TClass(name.clone()),
+            Def::DGlobal(ref t, _, _, _) |
+            Def::DConst(ref t, _, _, _) => t.clone(),
+            Def::DGeneric(_, ref fun) => fun.get_type(),
+            Def::DEnum(ref name, _, _) => Type::TClass(name.clone()),
+        }
+    }
+
+    fn get_name(&self) -> String {
+        match *self {
+            Def::DFun(_, ref name, _, _, _) |
+            Def::DClass(ref name, _, _, _, _) |
+            Def::DGlobal(_, ref name, _, _) |
+            Def::DConst(_, ref name, _, _) |
+            Def::DEnum(ref name, _, _) => name.clone(),
+            Def::DGeneric(_, ref fun) => fun.get_name(),
         }
     }
 }
 
+impl Variant {
+    fn get_type(&self, enum_name: &String) -> Type {
+        Type::TFunc(Box::new(Type::TClass(enum_name.clone())), self.1.iter().map(|arg| arg.0.clone()).collect())
+    }
+}
+
 impl TypeCheck<()> for Stmt {
+    fn location(&self) -> Option<Span> {
+        Some(self.span())
+    }
+
+    fn recovered(&self) -> Option<()> {
+        Some(())
+    }
+
     fn do_check(&self, context: &mut TCContext) -> TError<()> {
         match *self {
-            Stmt::SExpr(ref expr) => {
+            Stmt::SExpr(ref expr, _) => {
                 expr.check(context)?;
             },
-            Stmt::SAss(ref var, ref expr) => {
-                expect_one_of(context.get(var)?, expr.check(context)?, vec![Type::TInt, Type::TString, Type::TBool])?;
+            Stmt::SAss(ref lhs, ref expr, _) => {
+                let lhs_type = check_lvalue(lhs, context)?;
+                expect(expr.check(context)?, lhs_type, context)?;
             },
-            Stmt::SDecl(ref decl_type, ref decls) => {
-                if decl_type == &Type::TVoid {
-                    return Err(ErrStack::void_declaration())
-                }
+            Stmt::SDecl(ref decl_type, ref decls, span) => {
+                let decl_type = if decl_type == &Type::TVoid {
+                    context.report(ErrStack::void_declaration().at(span));
+                    Type::TError
+                } else if let Err(err) = check_type(decl_type, context) {
+                    context.report(err.at(span));
+                    Type::TError
+                } else {
+                    decl_type.clone()
+                };
                 for item in decls {
-                    check_decl(item, decl_type, context)?;
+                    if let Err(err) = check_decl(item, &decl_type, context) {
+                        context.report(err.at(item.span()));
+                    }
+                }
+            },
+            Stmt::SInc(ref var, _) |
+            Stmt::SDecr(ref var, _) => {
+                if context.is_const(var) {
+                    return Err(ErrStack::assignment_to_const(var));
                 }
+                expect(context.get(var)?, Type::TInt, context)?;
+            },
+            Stmt::SOpAss(ref var, op, ref expr, span) => {
+                let var_type = check_lvalue(&Expr::EVar(var.clone(), span), context)?;
+                let result_type = check_op(var_type.clone(), op, expr.check(context)?, context)?;
+                expect(result_type, var_type, context)?;
+            },
+            Stmt::SIf(ref cond, ref block, _) => {
+                check_condition(cond, context)?;
+                context.in_new_scope(|mut ctx| block.check(&mut ctx))?;
             },
-            Stmt::SInc(ref var) |
-            Stmt::SDecr(ref var) => {
-                expect(context.get(var)?, Type::TInt)?;
+            Stmt::SWhile(ref cond, ref block, _) => {
+                check_condition(cond, context)?;
+                context.in_new_loop(|mut ctx| block.check(&mut ctx))?;
             },
-            Stmt::SIf(ref cond, ref block) |
-            Stmt::SWhile(ref cond, ref block) => {
-                expect(cond.check(context)?, Type::TBool)?;
-                context.in_new_scope(|mut ctx| block.do_check(&mut ctx))?;
+            Stmt::SFor(ref init, ref cond, ref step, ref block, _) => {
+                context.in_new_scope(|ctx| {
+                    init.check(ctx)?;
+                    check_condition(cond, ctx)?;
+                    step.check(ctx)?;
+                    ctx.in_new_loop(|mut ctx| block.check(&mut ctx))
+                })?;
+            },
+            Stmt::SForEach(ref elem_type, ref var, ref array, ref block, span) => {
+                let elem_type = match check_type(elem_type, context) {
+                    Ok(()) => elem_type.clone(),
+                    Err(err) => {
+                        context.report(err.at(span));
+                        Type::TError
+                    },
+                };
+                let array_type = array.check(context)?;
+                if let Err(err) = expect(array_type, Type::TArray(Box::new(elem_type.clone())), context) {
+                    context.report(err.at(array.span()));
+                }
+                context.in_new_loop(|mut ctx| {
+                    ctx.add_local(var, &elem_type, span)?;
+                    block.check(&mut ctx)
+                })?;
             },
-            Stmt::SIfElse(ref cond, ref if_block, ref else_block) => {
-                expect(cond.check(context)?, Type::TBool)?;
-                context.in_new_scope(|mut ctx| if_block.do_check(&mut ctx))?;
-                context.in_new_scope(|mut ctx| else_block.do_check(&mut ctx))?;
+            Stmt::SIfElse(ref cond, ref if_block, ref else_block, _) => {
+                check_condition(cond, context)?;
+                context.in_new_scope(|mut ctx| if_block.check(&mut ctx))?;
+                context.in_new_scope(|mut ctx| else_block.check(&mut ctx))?;
             },
-            Stmt::SVRet => {
-                expect(context.return_type(), Type::TVoid)?;
+            Stmt::SVRet(_) => {
+                expect(context.return_type(), Type::TVoid, context)?;
             },
-            Stmt::SRet(ref expr) => {
+            Stmt::SRet(ref expr, _) => {
                 if context.return_type() == Type::TVoid {
                     return Err(ErrStack::void_return_value())
                 }
-                expect(expr.check(context)?, context.return_type())?;
+                expect(expr.check(context)?, context.return_type(), context)?;
             },
-            Stmt::SBlock(ref stmts) => {
+            Stmt::SBlock(ref stmts, _) => {
                 context.in_new_scope(|mut ctx| {
                     for stmt in stmts {
                         stmt.check(ctx)?;
@@ -171,76 +459,345 @@ impl TypeCheck<()> for Stmt {
                     Ok(())
                 })?;
             },
-            Stmt::Empty => (),
+            Stmt::SMatch(ref expr, ref cases, _) => {
+                let enum_type = expr.check(context)?;
+                let variants = match enum_type {
+                    Type::TClass(ref name) if context.has_enum(name) => Some(context.get_variants(name)),
+                    Type::TError => None,
+                    ref t => return Err(ErrStack::not_an_enum(t.clone()).at(expr.span())),
+                };
+                let mut matched = vec![];
+                for &Case(ref pattern, ref stmt) in cases {
+                    let result = match variants {
+                        Some(ref variants) => check_pattern(pattern, &enum_type, variants, &mut matched),
+                        None => Ok(pattern.failed_bindings()),
+                    };
+                    let bindings = result.unwrap_or_else(|err| {
+                        context.report(err.at(pattern.span()));
+                        pattern.failed_bindings()
+                    });
+                    context.in_new_scope(|ctx| {
+                        for &(ref id, ref t) in &bindings {
+                            ctx.add_local(id, t, pattern.span()).map_err(|err| err.at(pattern.span()))?;
+                        }
+                        stmt.check(ctx)
+                    })?;
+                }
+                if let Some(ref variants) = variants {
+                    let missing = variants.iter()
+                        .map(|variant| variant.0.clone())
+                        .filter(|variant| !matched.contains(variant))
+                        .collect::<Vec<String>>();
+                    if !missing.is_empty() {
+                        return Err(ErrStack::non_exhaustive_match(&missing));
+                    }
+                }
+            },
+            Stmt::SThrow(ref expr, _) => {
+                match expr.check(context)? {
+                    Type::TString | Type::TError => (),
+                    t => return Err(ErrStack::invalid_exception(t).at(expr.span())),
+                }
+            },
+            Stmt::STry(ref block, Arg(ref t, ref id, span), ref handler, _) => {
+                context.in_new_scope(|mut ctx| block.check(&mut ctx))?;
+                let t = match *t {
+                    Type::TString => Type::TString,
+                    ref t => {
+                        context.report(ErrStack::invalid_exception(t.clone()).at(span));
+                        Type::TError
+                    },
+                };
+                context.in_new_scope(|ctx| {
+                    ctx.add_local(id, &t, span).map_err(|err| err.at(span))?;
+                    handler.check(ctx)
+                })?;
+            },
+            Stmt::SBreak(_) => {
+                if !context.in_loop() {
+                    return Err(ErrStack::jump_outside_loop("break"))
+                }
+            },
+            Stmt::SContinue(_) => {
+                if !context.in_loop() {
+                    return Err(ErrStack::jump_outside_loop("continue"))
+                }
+            },
+            Stmt::Empty(_) => (),
         };
         Ok(())
     }
 }
 
+// Variables bound by the pattern with their types, the variants it covers are added to `matched`
+fn check_pattern(pattern: &Pattern, enum_type: &Type, variants: &Vec<(String, Vec<Type>)>,
+                 matched: &mut Vec<String>) -> TError<Vec<(String, Type)>> {
+    match *pattern {
+        Pattern::PVariant(ref variant, ref bindings, _) => {
+            let fields = match variants.iter().find(|v| &v.0 == variant) {
+                Some(v) => v.1.clone(),
+                None => return Err(ErrStack::no_variant(enum_type.clone(), variant)),
+            };
+            if bindings.len() != fields.len() {
+                return Err(ErrStack::invalid_binding_number(variant, bindings.len(), fields.len()));
+            }
+            if matched.contains(variant) {
+                return Err(ErrStack::unreachable_arm());
+            }
+            matched.push(variant.clone());
+            Ok(bindings.iter().cloned().zip(fields).filter(|binding| binding.0 != "_").collect())
+        },
+        Pattern::PWildcard(_) => {
+            let rest = variants.iter()
+                .map(|variant| variant.0.clone())
+                .filter(|variant| !matched.contains(variant))
+                .collect::<Vec<String>>();
+            if rest.is_empty() {
+                return Err(ErrStack::unreachable_arm());
+            }
+            matched.extend(rest);
+            Ok(vec![])
+        },
+    }
+}
+
+impl Pattern {
+    // The arm is still checked when its pattern is wrong, with bindings of unknown type
+    fn failed_bindings(&self) -> Vec<(String, Type)> {
+        match *self {
+            Pattern::PVariant(_, ref bindings, _) =>
+                bindings.iter().filter(|id| *id != "_").map(|id| (id.clone(), Type::TError)).collect(),
+            Pattern::PWildcard(_) => vec![],
+        }
+    }
+}
+
+// A condition of the wrong type is reported without abandoning the rest of the statement
+fn check_condition(cond: &Expr, context: &mut TCContext) -> TError<()> {
+    let cond_type = cond.check(context)?;
+    if let Err(err) = expect(cond_type, Type::TBool, context) {
+        context.report(err.at(cond.span()));
+    }
+    Ok(())
+}
+
+fn check_type(t: &Type, context: &TCContext) -> TError<()> {
+    match *t {
+        Type::TArray(ref elem_type) if **elem_type == Type::TVoid => Err(ErrStack::void_array()),
+        Type::TArray(ref elem_type) => check_type(elem_type, context),
+        Type::TClass(ref name) if !context.has_class(name) && !context.has_enum(name) && !context.is_type_param(t) =>
+            Err(ErrStack::undeclared_class(name)),
+        Type::TFunc(ref ret_type, ref args) => {
+            check_type(ret_type, context)?;
+            for arg in args {
+                if *arg == Type::TVoid {
+                    return Err(ErrStack::void_argument());
+                }
+                check_type(arg, context)?;
+            }
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+fn check_lvalue(expr: &Expr, context: &mut TCContext) -> TError<Type> {
+    match *expr {
+        Expr::EVar(ref id, _) if context.is_const(id) => Err(ErrStack::assignment_to_const(id)),
+        Expr::EVar(ref id, _) if context.kind(id).map_or(false, |kind| !kind.is_variable()) =>
+            Err(ErrStack::not_assignable(expr)),
+        Expr::EVar(_, _) |
+        Expr::EIndex(_, _, _) |
+        Expr::EField(_, _, _) => match expr.check(context)? {
+            Type::TInt if is_array_length(expr, context)? => Err(ErrStack::not_assignable(expr)),
+            t => Ok(t),
+        },
+        _ => Err(ErrStack::not_assignable(expr)),
+    }
+}
+
+fn is_array_length(expr: &Expr, context: &mut TCContext) -> TError<bool> {
+    match *expr {
+        Expr::EField(ref array, _, _) => match array.check(context)? {
+            Type::TArray(_) => Ok(true),
+            _ => Ok(false),
+        },
+        _ => Ok(false),
+    }
+}
+
+// The variable is declared even if its initializer is wrong, to avoid cascading errors
 fn check_decl(item: &Item, decl_type: &Type, context: &mut TCContext) -> TError<()> {
-    match *item {
-        Item::NoInit(ref var) => context.add(var, decl_type),
-        Item::Init(ref var, ref expr) => {
-            expect(expr.check(context)?, decl_type.clone())?;
-            context.add(var, decl_type)
+    if let Item::Init(_, ref expr, span) = *item {
+        if let Err(err) = expect(expr.check(context)?, decl_type.clone(), context) {
+            context.report(err.at(span));
         }
     }
+    context.add_local(&item.get_id(), decl_type, item.span())
 }
 
 impl TypeCheck<Type> for Expr {
+    fn location(&self) -> Option<Span> {
+        Some(self.span())
+    }
+
+    fn recovered(&self) -> Option<Type> {
+        Some(Type::TError)
+    }
+
+    fn checked(&self, t: &Type, context: &mut TCContext) {
+        context.record(|info| info.add_type(self, t));
+    }
+
     fn do_check(&self, context: &mut TCContext) -> TError<Type> {
+        if let Expr::EVar(ref id, span) = *self {
+            if let Some(kind) = context.kind(id) {
+                context.record(|info| info.add_symbol(span, kind));
+            }
+        }
         match *self {
-            Expr::EVar(ref var) => Ok(context.get(var)?),
-            Expr::EBoolLit(_) => Ok(Type::TBool),
-            Expr::EIntLit(_) => Ok(Type::TInt),
-            Expr::EStringLit(_) => Ok(Type::TString),
-            Expr::ENeg(ref expr) => expect(expr.do_check(context)?, Type::TInt),
-            Expr::ENot(ref expr) => expect(expr.do_check(context)?, Type::TBool),
-            Expr::EOp(ref lhs, op, ref rhs) => {
+            Expr::EVar(ref var, span) if context.kind(var) == Some(Kind::Method) =>
+                Err(ErrStack::method_as_value(var).at(span)),
+            Expr::EVar(ref var, span) if context.kind(var) == Some(Kind::Generic) =>
+                Err(ErrStack::generic_as_value(var).at(span)),
+            // Variants without fields are values, the others have to be called
+            Expr::EVar(ref var, span) if context.kind(var) == Some(Kind::Variant) => match context.get(var)? {
+                Type::TFunc(enum_type, ref fields) if fields.is_empty() => Ok(*enum_type),
+                _ => Err(ErrStack::variant_as_value(var).at(span)),
+            },
+            Expr::EVar(ref var, span) => Ok(context.get(var).map_err(|err| err.at(span))?),
+            Expr::EBoolLit(_, _) => Ok(Type::TBool),
+            Expr::EIntLit(_, _) => Ok(Type::TInt),
+            Expr::ELongLit(_, _) => Ok(Type::TLong),
+            Expr::EDoubleLit(_, _) => Ok(Type::TDouble),
+            Expr::EStringLit(_, _) => Ok(Type::TString),
+            Expr::ENeg(ref expr, _) => match expr.do_check(context)? {
+                t @ Type::TInt | t @ Type::TLong | t @ Type::TDouble => Ok(t),
+                ref t if context.is_type_param(t) => Ok(t.clone()),
+                t => expect(t, Type::TInt, context),
+            },
+            Expr::ENot(ref expr, _) => match expr.do_check(context)? {
+                ref t if context.is_type_param(t) => Ok(Type::TBool),
+                t => expect(t, Type::TBool, context),
+            },
+            Expr::EBitNot(ref expr, _) => match expr.do_check(context)? {
+                ref t if context.is_type_param(t) => Ok(Type::TInt),
+                t => expect(t, Type::TInt, context),
+            },
+            Expr::EOp(ref lhs, op, ref rhs, _) => {
                 let lhs_type = lhs.do_check(context)?;
                 let rhs_type = rhs.do_check(context)?;
-                match op {
-                    BinOp::Sub |
-                    BinOp::Mul |
-                    BinOp::Div |
-                    BinOp::Mod =>
-                        expect(lhs_type, Type::TInt).and(expect(rhs_type, Type::TInt)),
-                    BinOp::GE |
-                    BinOp::GT |
-                    BinOp::LE |
-                    BinOp::LT => {
-                        expect(lhs_type, Type::TInt).and(expect(rhs_type, Type::TInt))?;
-                        Ok(Type::TBool)
-                    }
-                    BinOp::Add =>
-                        expect_one_of(lhs_type, rhs_type, vec![Type::TInt, Type::TString]),
-                    BinOp::And | BinOp::Or =>
-                        expect(lhs_type, Type::TBool).and(expect(rhs_type, Type::TBool)),
-                    BinOp::EQ | BinOp::NEQ => {
-                        expect_one_of(lhs_type, rhs_type, vec![Type::TInt, Type::TString, Type::TBool])?;
-                        Ok(Type::TBool)
+                check_op(lhs_type, op, rhs_type, context)
+            },
+            Expr::ENewArray(ref elem_type, ref size, _) => {
+                check_type(&Type::TArray(Box::new(elem_type.clone())), context)?;
+                expect(size.check(context)?, Type::TInt, context)?;
+                Ok(Type::TArray(Box::new(elem_type.clone())))
+            },
+            Expr::EIndex(ref array, ref index, _) => {
+                let array_type = array.check(context)?;
+                expect(index.check(context)?, Type::TInt, context)?;
+                match array_type {
+                    Type::TArray(elem_type) => Ok(*elem_type),
+                    Type::TError => Ok(Type::TError),
+                    t => Err(ErrStack::not_an_array(t)),
+                }
+            },
+            Expr::EField(ref expr, ref field, _) => {
+                match expr.check(context)? {
+                    Type::TArray(_) if field == "length" => Ok(Type::TInt),
+                    Type::TClass(ref class) if context.has_class(class) => context.get_field(class, field),
+                    Type::TError => Ok(Type::TError),
+                    t => Err(ErrStack::no_field(t, field)),
+                }
+            },
+            Expr::ENew(ref class, _) => {
+                if context.has_enum(class) {
+                    return Err(ErrStack::undeclared_class(class));
+                }
+                let t = Type::TClass(class.clone());
+                check_type(&t, context)?;
+                Ok(t)
+            },
+            Expr::ENull(ref t, _) => {
+                check_type(t, context)?;
+                match *t {
+                    Type::TClass(_) |
+                    Type::TArray(_) |
+                    Type::TFunc(_, _) => Ok(t.clone()),
+                    _ => Err(ErrStack::invalid_null(t)),
+                }
+            },
+            Expr::EPredef(ref predef, _) => predef.do_check(context),
+            Expr::EApp(ref fun, ref args, span) => {
+                let fun_type = context.get(fun)?;
+                if let Some(kind) = context.kind(fun) {
+                    context.record(|info| info.add_symbol(span, kind));
+                }
+                let type_params = context.type_params(fun);
+                check_function_call(fun, fun_type, &type_params, args, context)
+            },
+            Expr::ELambda(ref args, ref body, ref captures, _) => {
+                for arg in args {
+                    arg.do_check(context).map_err(|err| err.at(arg.2))?;
+                }
+                let arg_types = args.iter().map(|arg| arg.0.clone()).collect();
+                match context.in_new_lambda(args, captures, |ctx| body.check(ctx))? {
+                    Type::TError => Ok(Type::TError),
+                    ret_type => Ok(Type::TFunc(Box::new(ret_type), arg_types)),
+                }
+            },
+            Expr::EMethodCall(ref object, ref method, ref args, _) => {
+                match object.check(context)? {
+                    Type::TClass(ref class) if context.has_class(class) => {
+                        let method_type = context.get_method(class, method)?;
+                        check_function_call(method, method_type, &vec![], args, context)
                     },
+                    Type::TError => Ok(Type::TError),
+                    t => Err(ErrStack::no_method(t, method)),
                 }
             },
-            Expr::EPredef(ref predef) => predef.do_check(context),
-            Expr::EApp(ref fun, ref args) => check_function_call(fun, args, context),
         }
     }
 }
 
-fn check_function_call(fun: &String, args: &Vec<Expr>, context: &mut TCContext) -> TError<Type> {
-    if let Type::TFunc(ret_type, expected_types) = context.get(fun)? {
+// Type arguments of generic functions are inferred from the types of the arguments
+fn check_function_call(fun: &String, fun_type: Type, type_params: &Vec<String>, args: &Vec<Expr>,
+                       context: &mut TCContext) -> TError<Type> {
+    if let Type::TFunc(ret_type, expected_types) = fun_type {
         if args.len() != expected_types.len() {
             return Err(ErrStack::invalid_argument_number(fun, args.len(), expected_types.len()));
         }
 
-        for (number, (ref actual_type, ref arg)) in expected_types.into_iter().zip(args).enumerate() {
-            let arg_type = arg.check(context)?;
-            if arg_type.clone() != actual_type.clone() {
-                return Err(ErrStack::invalid_call_type(fun, number, arg_type, actual_type.clone()));
+        let mut arg_types = vec![];
+        for arg in args {
+            arg_types.push(arg.check(context)?);
+        }
+        if !type_params.is_empty() && arg_types.contains(&Type::TError) {
+            return Ok(Type::TError);
+        }
+        let bindings = infer_type_args(type_params, &expected_types, &arg_types);
+        let mut type_args = vec![];
+        for type_param in type_params {
+            match bindings.get(type_param) {
+                Some(&Type::TVoid) => return Err(ErrStack::void_argument()),
+                Some(t) => type_args.push(t.clone()),
+                None => return Err(ErrStack::cannot_infer(fun, type_param)),
+            }
+        }
+
+        for (number, (ref actual_type, arg_type)) in expected_types.into_iter().zip(arg_types).enumerate() {
+            let actual_type = actual_type.substitute(&bindings);
+            if !is_assignable(&arg_type, &actual_type, context) {
+                return Err(ErrStack::invalid_call_type(fun, number, arg_type, actual_type));
             }
         }
-        Ok(*ret_type)
+        if !type_params.is_empty() {
+            context.add_instantiation(fun, type_args);
+        }
+        Ok((*ret_type).substitute(&bindings))
+    } else if fun_type == Type::TError {
+        Ok(Type::TError)
     } else {
         Err(ErrStack::not_a_function(fun))
     }
@@ -251,31 +808,103 @@ impl TypeCheck<Type> for Predef {
         match *self {
             Predef::Error => Ok(Type::TVoid),
             Predef::ReadInt => Ok(Type::TInt),
+            Predef::ReadDouble => Ok(Type::TDouble),
             Predef::ReadString => Ok(Type::TString),
             Predef::PrintInt(ref arg) => {
-                expect(arg.check(context)?, Type::TInt)?;
+                expect(arg.check(context)?, Type::TInt, context)?;
+                Ok(Type::TVoid)
+            },
+            Predef::PrintLong(ref arg) => {
+                expect(arg.check(context)?, Type::TLong, context)?;
                 Ok(Type::TVoid)
             },
+            Predef::PrintDouble(ref arg) => {
+                expect(arg.check(context)?, Type::TDouble, context)?;
+                Ok(Type::TVoid)
+            },
+            // Numeric values are never converted implicitly
+            Predef::Convert(ref t, ref arg) => match arg.check(context)? {
+                Type::TInt | Type::TLong | Type::TDouble | Type::TError => Ok(t.clone()),
+                arg_type => Err(ErrStack::incompatible(arg_type, t.clone())),
+            },
             Predef::PrintString(ref arg) => {
-                expect(arg.check(context)?, Type::TString)?;
+                expect(arg.check(context)?, Type::TString, context)?;
                 Ok(Type::TVoid)
             },
         }
     }
 }
 
+// Operators on values of type parameters are checked for each instantiation
+fn check_op(lhs_type: Type, op: BinOp, rhs_type: Type, context: &TCContext) -> TError<Type> {
+    if context.is_type_param(&lhs_type) || context.is_type_param(&rhs_type) {
+        let param_type = if context.is_type_param(&lhs_type) { lhs_type } else { rhs_type };
+        return Ok(match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => param_type,
+            BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Shl | BinOp::Shr => Type::TInt,
+            _ => Type::TBool,
+        });
+    }
+    match op {
+        BinOp::Sub |
+        BinOp::Mul |
+        BinOp::Div =>
+            expect_one_of(lhs_type, rhs_type, vec![Type::TInt, Type::TLong, Type::TDouble]),
+        BinOp::Mod =>
+            expect_one_of(lhs_type, rhs_type, vec![Type::TInt, Type::TLong]),
+        BinOp::BitAnd |
+        BinOp::BitOr |
+        BinOp::BitXor |
+        BinOp::Shl |
+        BinOp::Shr =>
+            expect_one_of(lhs_type, rhs_type, vec![Type::TInt]),
+        BinOp::GE |
+        BinOp::GT |
+        BinOp::LE |
+        BinOp::LT => {
+            expect_one_of(lhs_type, rhs_type, vec![Type::TInt, Type::TLong, Type::TDouble, Type::TString])?;
+            Ok(Type::TBool)
+        }
+        BinOp::Add =>
+            expect_one_of(lhs_type, rhs_type, vec![Type::TInt, Type::TLong, Type::TDouble, Type::TString]),
+        BinOp::And | BinOp::Or =>
+            expect(lhs_type, Type::TBool, context).and(expect(rhs_type, Type::TBool, context)),
+        BinOp::EQ | BinOp::NEQ => {
+            match (lhs_type, rhs_type) {
+                (Type::TClass(ref lhs), Type::TClass(ref rhs))
+                    if context.is_subclass(lhs, rhs) || context.is_subclass(rhs, lhs) => (),
+                (lhs_type, rhs_type) => {
+                    expect_one_of(lhs_type, rhs_type,
+                                  vec![Type::TInt, Type::TLong, Type::TDouble, Type::TString, Type::TBool])?;
+                },
+            };
+            Ok(Type::TBool)
+        },
+    }
+}
+
 fn expect_one_of(lhs: Type, rhs: Type, expected: Vec<Type>) -> TError<Type> {
-    if lhs == rhs && expected.contains(&lhs) {
+    if lhs == Type::TError || rhs == Type::TError {
+        Ok(Type::TError)
+    } else if lhs == rhs && expected.contains(&lhs) {
         Ok(lhs)
     } else {
         Err(ErrStack::op_not_defined(lhs, rhs))
     }
 }
 
-fn expect(given: Type, expected: Type) -> TError<Type> {
-    if given == expected {
+fn expect(given: Type, expected: Type, context: &TCContext) -> TError<Type> {
+    if is_assignable(&given, &expected, context) {
         Ok(expected)
     } else {
         Err(ErrStack::incompatible(given, expected))
     }
+}
+
+fn is_assignable(given: &Type, expected: &Type, context: &TCContext) -> bool {
+    match (given, expected) {
+        (&Type::TError, _) | (_, &Type::TError) => true,
+        (&Type::TClass(ref class), &Type::TClass(ref ancestor)) => context.is_subclass(class, ancestor),
+        _ => given == expected,
+    }
 }
\ No newline at end of file
@@ -1,54 +1,441 @@
 use std::collections::HashMap;
-use ast::Type;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::fmt;
+use std::mem;
+use ast::{Type, Field, Arg, Def, Span, Expr, Captures};
 use semantic_analysis::errors::{TError, ErrStack};
+use semantic_analysis::type_info::TypeInfo;
 
-type Env = HashMap<String, (Type, bool)>;
+type Env = HashMap<String, (Type, bool, Kind)>;
+type Classes = HashMap<String, Class>;
+type Consts = HashMap<String, Option<Expr>>;
+// Enum -> (Variant name, Field types), in declaration order
+type Enums = HashMap<String, Vec<(String, Vec<Type>)>>;
+// Generic function -> (Type parameters, Function)
+type Generics = HashMap<String, (Vec<String>, Def)>;
+// Generic function and its type arguments
+type Instantiation = (String, Vec<Type>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Var,
+    // Local variable, with the number of lambdas around its declaration
+    Local(usize),
+    Field,
+    Function,
+    Generic,
+    Method,
+    Variant,
+}
+
+impl Kind {
+    pub fn is_variable(&self) -> bool {
+        match *self {
+            Kind::Var | Kind::Local(_) | Kind::Field => true,
+            Kind::Function | Kind::Generic | Kind::Method | Kind::Variant => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Class {
+    parent: Option<String>,
+    fields: Vec<Field>,
+    // Method name -> Type, without inherited methods
+    methods: Vec<(String, Type)>,
+}
 
 #[derive(Clone)]
 pub struct TCContext {
-    // Identifier -> (Type, IsFromCurrentScope)
+    // Identifier -> (Type, IsFromCurrentScope, Kind)
     env: Env,
+    // Variable -> Span of its declaration
+    declarations: HashMap<String, Span>,
+    classes: Classes,
+    enums: Enums,
+    // Constant -> Folded value, once its initializer is checked
+    consts: Consts,
     return_type: Type,
+    in_loop: bool,
+    // Type parameters of the generic function being checked, opaque types in its body
+    type_params: Vec<String>,
+    // Errors recovered from so far, shared by all nested contexts
+    reported: Rc<RefCell<Vec<ErrStack>>>,
+    // Shared by all nested contexts, None while checking instances of generic functions
+    info: Option<Rc<RefCell<TypeInfo>>>,
+    // Captures of the lambdas being checked, from the outermost one
+    lambdas: Vec<Rc<RefCell<Vec<String>>>>,
+    generics: Rc<RefCell<Generics>>,
+    // Instantiations of generic functions in the order they were first used
+    instantiations: Rc<RefCell<Vec<Instantiation>>>,
 }
 
 impl TCContext {
     pub fn new() -> TCContext {
         TCContext {
             env: Env::new(),
+            declarations: HashMap::new(),
+            classes: Classes::new(),
+            enums: Enums::new(),
+            consts: Consts::new(),
             return_type: Type::TVoid,
+            in_loop: false,
+            type_params: vec![],
+            reported: Rc::new(RefCell::new(vec![])),
+            info: Some(Rc::new(RefCell::new(TypeInfo::default()))),
+            lambdas: vec![],
+            generics: Rc::new(RefCell::new(Generics::new())),
+            instantiations: Rc::new(RefCell::new(vec![])),
         }
     }
 
+    pub fn report(&self, err: ErrStack) {
+        self.reported.borrow_mut().push(err);
+    }
+
+    pub fn reported_count(&self) -> usize {
+        self.reported.borrow().len()
+    }
+
+    pub fn add_to_reported_stacks<T: fmt::Display>(&self, from: usize, within: &T, span: Option<Span>) {
+        let mut reported = self.reported.borrow_mut();
+        let errors = reported.split_off(from);
+        reported.extend(errors.into_iter().map(|err| err.add_to_stack(within, span)));
+    }
+
+    pub fn take_reported(&self) -> Vec<ErrStack> {
+        self.reported.borrow_mut().drain(..).collect()
+    }
+
+    pub fn record<F>(&self, fun: F) where F: FnOnce(&mut TypeInfo) {
+        if let Some(ref info) = self.info {
+            fun(&mut info.borrow_mut());
+        }
+    }
+
+    pub fn take_info(&self) -> TypeInfo {
+        self.info.as_ref().map_or(TypeInfo::default(), |info| mem::replace(&mut info.borrow_mut(), TypeInfo::default()))
+    }
+
+    // Instances of generic functions are checked without recording, their bodies are already recorded
+    pub fn without_info(&self) -> TCContext {
+        let mut new_env = self.clone();
+        new_env.info = None;
+        new_env
+    }
+
+    pub fn add_class(&mut self, name: &String, parent: &Option<String>, fields: &Vec<Field>,
+                     methods: Vec<(String, Type)>) -> TError<()> {
+        if self.classes.contains_key(name) {
+            return Err(ErrStack::class_redefinition(name));
+        }
+        if self.enums.contains_key(name) {
+            return Err(ErrStack::type_redefinition(name));
+        }
+        self.classes.insert(name.clone(), Class {
+            parent: parent.clone(),
+            fields: fields.clone(),
+            methods: methods,
+        });
+        Ok(())
+    }
+
+    pub fn has_class(&self, name: &String) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    pub fn add_enum(&mut self, name: &String, variants: Vec<(String, Vec<Type>)>) -> TError<()> {
+        if self.classes.contains_key(name) || self.enums.contains_key(name) {
+            return Err(ErrStack::type_redefinition(name));
+        }
+        self.enums.insert(name.clone(), variants);
+        Ok(())
+    }
+
+    // Variants are constructed by calling them like functions returning the enum
+    pub fn add_variant(&mut self, id: &String, t: &Type) -> TError<()> {
+        self.add_binding(id, t, Kind::Variant)
+    }
+
+    pub fn has_enum(&self, name: &String) -> bool {
+        self.enums.contains_key(name)
+    }
+
+    pub fn get_variants(&self, name: &String) -> Vec<(String, Vec<Type>)> {
+        self.enums[name].clone()
+    }
+
+    pub fn get_parent(&self, class: &String) -> Option<String> {
+        self.classes.get(class).and_then(|c| c.parent.clone())
+    }
+
+    pub fn check_hierarchy(&self, class: &String) -> TError<()> {
+        let mut visited = vec![class.clone()];
+        let mut current = self.get_parent(class);
+        while let Some(parent) = current {
+            if !self.has_class(&parent) {
+                return Err(ErrStack::undeclared_class(&parent));
+            }
+            if visited.contains(&parent) {
+                return Err(ErrStack::cyclic_inheritance(class));
+            }
+            current = self.get_parent(&parent);
+            visited.push(parent);
+        }
+        Ok(())
+    }
+
+    pub fn is_subclass(&self, class: &String, ancestor: &String) -> bool {
+        let mut current = Some(class.clone());
+        while let Some(name) = current {
+            if &name == ancestor {
+                return true;
+            }
+            current = self.get_parent(&name);
+        }
+        false
+    }
+
+    pub fn get_field(&self, class: &String, field: &String) -> TError<Type> {
+        let mut current = Some(class.clone());
+        while let Some(name) = current {
+            if let Some(f) = self.classes[&name].fields.iter().find(|f| &f.1 == field) {
+                return Ok(f.0.clone());
+            }
+            current = self.get_parent(&name);
+        }
+        Err(ErrStack::no_field(Type::TClass(class.clone()), field))
+    }
+
+    pub fn get_method(&self, class: &String, method: &String) -> TError<Type> {
+        let mut current = Some(class.clone());
+        while let Some(name) = current {
+            if let Some(m) = self.classes[&name].methods.iter().find(|m| &m.0 == method) {
+                return Ok(m.1.clone());
+            }
+            current = self.get_parent(&name);
+        }
+        Err(ErrStack::no_method(Type::TClass(class.clone()), method))
+    }
+
+    // Fields come with the span of their declaration
+    fn get_members(&self, class: &String) -> Vec<(String, Type, Kind, Option<Span>)> {
+        let mut members: Vec<(String, Type, Kind, Option<Span>)> = vec![];
+        let mut current = Some(class.clone());
+        while let Some(name) = current {
+            let info = &self.classes[&name];
+            let fields = info.fields.iter().map(|f| (f.1.clone(), f.0.clone(), Kind::Field, Some(f.2)));
+            let methods = info.methods.iter().map(|m| (m.0.clone(), m.1.clone(), Kind::Method, None));
+            for (id, t, kind, span) in fields.chain(methods) {
+                if !members.iter().any(|m| m.0 == id) {
+                    members.push((id, t, kind, span));
+                }
+            }
+            current = info.parent.clone();
+        }
+        members
+    }
+
     pub fn return_type(&self) -> Type {
         self.return_type.clone()
     }
 
+    pub fn in_loop(&self) -> bool {
+        self.in_loop
+    }
+
     pub fn get(&self, id: &String) -> TError<Type> {
         match self.env.get(id) {
-            Some(t) => Ok(t.0.clone()),
+            Some(t) => {
+                self.capture(id, t.2);
+                Ok(t.0.clone())
+            },
             None => Err(ErrStack::undeclared(id)),
         }
     }
 
-    pub fn add(&mut self, id: &String, t: &Type) -> TError<()> {
+    // Locals declared outside of a lambda are captured by it, members of the class through self
+    fn capture(&self, id: &String, kind: Kind) {
+        let self_id = String::from("self");
+        let (id, depth) = match kind {
+            Kind::Local(depth) => (id, depth),
+            Kind::Field | Kind::Method => match self.kind(&self_id) {
+                Some(Kind::Local(depth)) => (&self_id, depth),
+                _ => return,
+            },
+            _ => return,
+        };
+        for captures in &self.lambdas[depth..] {
+            let mut captures = captures.borrow_mut();
+            if !captures.contains(id) {
+                captures.push(id.clone());
+            }
+        }
+    }
+
+    pub fn kind(&self, id: &String) -> Option<Kind> {
+        self.env.get(id).map(|binding| binding.2)
+    }
+
+    pub fn add(&mut self, id: &String, t: &Type, span: Span) -> TError<()> {
+        self.add_binding(id, t, Kind::Var)?;
+        self.declarations.insert(id.clone(), span);
+        Ok(())
+    }
+
+    // Shadowing a variable of an enclosing scope is allowed, and recorded for the lints
+    pub fn add_local(&mut self, id: &String, t: &Type, span: Span) -> TError<()> {
+        if let Some(&(_, false, kind)) = self.env.get(id) {
+            if let (true, Some(&outer)) = (kind.is_variable(), self.declarations.get(id)) {
+                self.record(|info| info.add_shadowing(id, span, outer));
+            }
+        }
+        let depth = self.lambdas.len();
+        self.add_binding(id, t, Kind::Local(depth))?;
+        self.declarations.insert(id.clone(), span);
+        Ok(())
+    }
+
+    pub fn add_function(&mut self, id: &String, t: &Type) -> TError<()> {
+        self.add_binding(id, t, Kind::Function)
+    }
+
+    pub fn add_generic(&mut self, id: &String, type_params: &Vec<String>, fun: &Def, t: &Type) -> TError<()> {
+        self.add_binding(id, t, Kind::Generic)?;
+        self.generics.borrow_mut().insert(id.clone(), (type_params.clone(), fun.clone()));
+        Ok(())
+    }
+
+    // Type parameters of the function, empty unless it is generic
+    pub fn type_params(&self, id: &String) -> Vec<String> {
+        match self.kind(id) {
+            Some(Kind::Generic) => self.generics.borrow()[id].0.clone(),
+            _ => vec![],
+        }
+    }
+
+    pub fn get_generic(&self, id: &String) -> (Vec<String>, Def) {
+        self.generics.borrow()[id].clone()
+    }
+
+    pub fn is_type_param(&self, t: &Type) -> bool {
+        match *t {
+            Type::TClass(ref name) => self.type_params.contains(name),
+            _ => false,
+        }
+    }
+
+    // Calls made by a generic function are instantiated together with it
+    pub fn add_instantiation(&self, id: &String, type_args: Vec<Type>) {
+        if !self.type_params.is_empty() {
+            return;
+        }
+        let instantiation = (id.clone(), type_args);
+        let mut instantiations = self.instantiations.borrow_mut();
+        if !instantiations.contains(&instantiation) {
+            instantiations.push(instantiation);
+        }
+    }
+
+    pub fn instantiation(&self, index: usize) -> Option<Instantiation> {
+        self.instantiations.borrow().get(index).cloned()
+    }
+
+    // self always refers to the receiver of the method, it can't be declared
+    fn add_binding(&mut self, id: &String, t: &Type, kind: Kind) -> TError<()> {
         match self.env.get(id) {
+            _ if id == "self" => Err(ErrStack::redefinition(id)),
             None |
-            Some(&(_, false)) => {
-                self.env.insert(id.clone(), (t.clone(), true));
+            Some(&(_, false, _)) => {
+                self.env.insert(id.clone(), (t.clone(), true, kind));
+                self.consts.remove(id);
                 Ok(())
             },
             _ => Err(ErrStack::redefinition(id))
         }
     }
 
+    pub fn add_const(&mut self, id: &String, t: &Type, span: Span) -> TError<()> {
+        self.add(id, t, span)?;
+        self.consts.insert(id.clone(), None);
+        Ok(())
+    }
+
+    pub fn is_const(&self, id: &String) -> bool {
+        self.consts.contains_key(id)
+    }
+
+    pub fn set_const_value(&mut self, id: &String, value: Expr) {
+        self.consts.insert(id.clone(), Some(value));
+    }
+
+    // Values of the constants whose initializers were already checked
+    pub fn const_values(&self) -> HashMap<String, Expr> {
+        self.consts.iter()
+            .filter_map(|(id, value)| value.as_ref().map(|value| (id.clone(), value.clone())))
+            .collect()
+    }
+
     pub fn in_new_function<T, F>(&self, ret_type: &Type, fun: F) -> T
         where F: Fn(&mut TCContext) -> T
     {
         let mut new_env = self.make_new_context();
         new_env.return_type = ret_type.clone();
+        new_env.in_loop = false;
+        fun(&mut new_env)
+    }
+
+    pub fn in_new_generic<T, F>(&self, type_params: &Vec<String>, ret_type: &Type, fun: F) -> T
+        where F: Fn(&mut TCContext) -> T
+    {
+        let mut new_env = self.make_new_context();
+        new_env.return_type = ret_type.clone();
+        new_env.in_loop = false;
+        new_env.type_params = type_params.clone();
+        fun(&mut new_env)
+    }
+
+    pub fn in_new_method<T, F>(&self, class: &String, ret_type: &Type, fun: F) -> T
+        where F: Fn(&mut TCContext) -> T
+    {
+        let mut new_env = self.make_new_context();
+        new_env.return_type = ret_type.clone();
+        new_env.in_loop = false;
+        for (id, t, kind, span) in self.get_members(class) {
+            new_env.consts.remove(&id);
+            match span {
+                Some(span) => new_env.declarations.insert(id.clone(), span),
+                None => new_env.declarations.remove(&id),
+            };
+            new_env.env.insert(id, (t, false, kind));
+        }
+        new_env.env.insert(String::from("self"), (Type::TClass(class.clone()), false, Kind::Local(0)));
         fun(&mut new_env)
     }
 
+    pub fn in_new_loop<T, F>(&self, fun: F) -> T
+        where F: Fn(&mut TCContext) -> T
+    {
+        let mut new_env = self.make_new_context();
+        new_env.in_loop = true;
+        fun(&mut new_env)
+    }
+
+    // Locals of the enclosing scopes stay visible, those used in the body are recorded as captures
+    pub fn in_new_lambda<T, F>(&self, args: &Vec<Arg>, captures: &Captures, fun: F) -> TError<T>
+        where F: Fn(&mut TCContext) -> TError<T>
+    {
+        let mut new_env = self.make_new_context();
+        new_env.lambdas.push(Rc::new(RefCell::new(vec![])));
+        for arg in args {
+            new_env.add_local(&arg.1, &arg.0, arg.2).map_err(|err| err.at(arg.2))?;
+        }
+        let result = fun(&mut new_env);
+        *captures.borrow_mut() = new_env.lambdas[self.lambdas.len()].borrow().clone();
+        result
+    }
+
     pub fn in_new_scope<T, F>(&self, fun: F) -> T
         where F: Fn(&mut TCContext) -> T
     {
@@ -58,8 +445,19 @@ impl TCContext {
 
     fn make_new_context(&self) -> TCContext {
         TCContext {
-            env: self.clone().env.into_iter().map(|(ref var, ref t)| (var.clone(), (t.0.clone(), false))).collect(),
+            env: self.clone().env.into_iter().map(|(ref var, ref t)| (var.clone(), (t.0.clone(), false, t.2))).collect(),
+            declarations: self.declarations.clone(),
+            classes: self.classes.clone(),
+            enums: self.enums.clone(),
+            consts: self.consts.clone(),
             return_type: self.return_type.clone(),
+            in_loop: self.in_loop,
+            type_params: self.type_params.clone(),
+            reported: self.reported.clone(),
+            info: self.info.clone(),
+            lambdas: self.lambdas.clone(),
+            generics: self.generics.clone(),
+            instantiations: self.instantiations.clone(),
         }
     }
 }
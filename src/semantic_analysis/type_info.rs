@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use ast::{Type, Expr, Span};
+use semantic_analysis::type_context::Kind;
+
+// What the type checker learned about a program, for the passes that run after it.
+// Bodies of generic functions are recorded once, with their type parameters as types.
+#[derive(Debug, Clone, Default)]
+pub struct TypeInfo {
+    // Expression -> Its type
+    types: HashMap<Span, Type>,
+    // Variable or call -> Kind of the identifier it refers to
+    symbols: HashMap<Span, Kind>,
+    // (Declaration, Variable) -> Declaration of the variable it shadows
+    shadowed: HashMap<(Span, String), Span>,
+}
+
+impl TypeInfo {
+    pub fn add_type(&mut self, expr: &Expr, t: &Type) {
+        self.types.insert(expr.span(), t.clone());
+    }
+
+    pub fn add_symbol(&mut self, span: Span, kind: Kind) {
+        self.symbols.insert(span, kind);
+    }
+
+    pub fn add_shadowing(&mut self, id: &String, span: Span, outer: Span) {
+        self.shadowed.insert((span, id.clone()), outer);
+    }
+
+    pub fn type_of(&self, expr: &Expr) -> Option<&Type> {
+        self.types.get(&expr.span())
+    }
+
+    pub fn kind_of(&self, expr: &Expr) -> Option<Kind> {
+        self.symbols.get(&expr.span()).cloned()
+    }
+
+    pub fn shadowed(&self, id: &String, span: Span) -> Option<Span> {
+        self.shadowed.get(&(span, id.clone())).cloned()
+    }
+}
@@ -1,57 +1,19 @@
-use std::fs::File;
-use std::env;
-use std::process::*;
-use std::io::Read;
-use std::path::Path;
-use std::io::Write;
+use std::cmp;
+use ast::Span;
 
-macro_rules! println_stderr(
-    ($($arg:tt)*) => { {
-        let r = writeln!(&mut ::std::io::stderr(), $($arg)*);
-        r.expect("failed printing to stderr");
-    } }
-);
-
-pub fn print_err(err: String) {
-    println_stderr!("ERROR");
-    println_stderr!("{}", err);
-    exit(-1);
-}
-
-pub fn get_input() -> String {
-    let mut to_parse = String::new();
-    if let Some(ref filename) = env::args().nth(1) {
-        if let Ok(mut input) = File::open(filename) {
-            input.read_to_string(&mut to_parse).unwrap();
-        } else {
-            print_err(format!("Couldn't open file {}", filename));
-        }
-    } else {
-        print_err(format!("Filename not given"));
-    }
-    to_parse
+pub fn line_and_column(source: &str, pos: usize) -> (usize, usize) {
+    let before = &source[..pos];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
 }
 
-pub fn get_output_directory() -> String {
-    let ref arg1 = env::args().nth(1).expect("Filename not given");
-    let path = Path::new(arg1);
-    let parent = path.parent().unwrap_or(Path::new("."));
-    if parent.to_str().unwrap() == "" {
-        String::from("./")
-    } else {
-        format!("{}/", parent.to_str().unwrap())
-    }
+// Source line containing the start of the span, with the span underlined
+// up to the end of that line
+pub fn excerpt(source: &str, span: Span) -> String {
+    let Span(beg, end) = span;
+    let line_start = source[..beg].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[beg..].find('\n').map_or(source.len(), |i| beg + i);
+    let indent: String = source[line_start..beg].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let width = source[beg..cmp::max(beg, cmp::min(end, line_end))].chars().count();
+    format!("{}\n{}{}\n", &source[line_start..line_end], indent, "^".repeat(cmp::max(width, 1)))
 }
-
-pub fn get_output_filename(extension: &str) -> String {
-    let no_ext = get_filename_no_ext();
-    let parent = get_output_directory();
-    format!("{}{}{}", parent, no_ext, extension)
-}
-
-pub fn get_filename_no_ext() -> String {
-    let ref arg1 = env::args().nth(1).expect("Filename not given");
-    let path = Path::new(arg1);
-    let no_ext = path.file_stem().expect("Error opening file");
-    format!("{}", no_ext.to_str().unwrap())
-}
\ No newline at end of file
@@ -0,0 +1,50 @@
+extern crate latte;
+
+use latte::CompileError;
+
+const GOOD: &'static str = "
+int fib(int n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+int main() {
+    printInt(fib(10));
+    return 0;
+}
+";
+
+#[test]
+fn compiles_valid_program() {
+    let mut out: Vec<u8> = vec![];
+    assert!(latte::compile(GOOD, &mut out).is_ok());
+    let ir = String::from_utf8(out).unwrap();
+    assert!(ir.contains("define i32 @main()"));
+    assert!(ir.contains("define i32 @fib(i32"));
+}
+
+#[test]
+fn reports_every_syntax_error() {
+    let source = "int main() {\n  int x = ;\n  x = 1 +;\n  return 0;\n}\n";
+    match latte::compile(source, &mut vec![]) {
+        Err(CompileError::Syntax(errors)) => assert_eq!(errors.len(), 2),
+        _ => panic!("expected syntax errors"),
+    }
+}
+
+#[test]
+fn reports_type_errors() {
+    let source = "int main() {\n  int x = \"one\";\n  return x;\n}\n";
+    match latte::compile(source, &mut vec![]) {
+        Err(CompileError::Semantic(err)) => assert!(err.report("test.lat", source).contains("test.lat:2:7")),
+        _ => panic!("expected a type error"),
+    }
+}
+
+#[test]
+fn folds_int_overflow_like_runtime() {
+    let source = "int main() {\n  printInt(2147483647 + 1);\n  if (-2147483647 - 2 > 0) printInt(1);\n  return 0;\n}\n";
+    let mut out: Vec<u8> = vec![];
+    assert!(latte::compile(source, &mut out).is_ok());
+    assert!(String::from_utf8(out).unwrap().contains("i32 -2147483648"));
+}